@@ -0,0 +1,128 @@
+//! Building the values of `Authorization` headers.
+
+use alloc::string::String;
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Returns the value of an `Authorization` header for the `Basic`
+/// scheme, see [RFC 7617](https://datatracker.ietf.org/doc/html/rfc7617).
+pub(crate) fn basic_auth(username: &str, password: Option<&str>) -> String {
+    let mut credentials = String::from(username);
+    credentials.push(':');
+    credentials.push_str(password.unwrap_or(""));
+    let mut value = String::from("Basic ");
+    value.push_str(&base64_encode(credentials.as_bytes()));
+    value
+}
+
+/// Returns the value of an `Authorization` header for the `Basic`
+/// scheme, from the percent-encoded `user[:password]` userinfo of a
+/// URL.
+#[cfg(feature = "std")]
+pub(crate) fn basic_auth_from_userinfo(userinfo: &str) -> String {
+    match userinfo.split_once(':') {
+        Some((username, password)) => {
+            basic_auth(&percent_decode(username), Some(&percent_decode(password)))
+        }
+        None => basic_auth(&percent_decode(userinfo), None),
+    }
+}
+
+/// Encodes `bytes` in padded base64, with the standard alphabet.
+fn base64_encode(bytes: &[u8]) -> String {
+    // `usize::div_ceil` needs Rust 1.73.
+    #[allow(clippy::manual_div_ceil)]
+    let mut encoded = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let sextets = [
+            b[0] >> 2,
+            (b[0] & 0x03) << 4 | b[1] >> 4,
+            (b[1] & 0x0f) << 2 | b[2] >> 6,
+            b[2] & 0x3f,
+        ];
+        for (i, sextet) in sextets.iter().enumerate() {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[*sextet as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodes `%XX` escapes. Invalid escapes are kept as-is, and invalid
+/// UTF-8 is replaced.
+#[cfg(feature = "std")]
+fn percent_decode(encoded: &str) -> String {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 3).and_then(|hex| {
+            let hex = core::str::from_utf8(hex).ok()?;
+            u8::from_str_radix(hex, 16).ok()
+        });
+        match escaped {
+            Some(byte) if bytes[i] == b'%' => {
+                decoded.push(byte);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64_encode, basic_auth};
+
+    #[test]
+    fn base64() {
+        // The test vectors from RFC 4648.
+        let encoded = ["", "f", "fo", "foo", "foob", "fooba", "foobar"]
+            .map(|input| base64_encode(input.as_bytes()));
+        assert_eq!(
+            encoded,
+            ["", "Zg==", "Zm8=", "Zm9v", "Zm9vYg==", "Zm9vYmE=", "Zm9vYmFy"]
+        );
+    }
+
+    #[test]
+    fn basic() {
+        // The example from RFC 7617.
+        assert_eq!(
+            basic_auth("Aladdin", Some("open sesame")),
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+        assert_eq!(basic_auth("user", None), "Basic dXNlcjo=");
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn userinfo() {
+        use super::basic_auth_from_userinfo;
+
+        assert_eq!(
+            basic_auth_from_userinfo("Aladdin:open%20sesame"),
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+        assert_eq!(basic_auth_from_userinfo("user"), "Basic dXNlcjo=");
+        assert_eq!(
+            basic_auth_from_userinfo("a%3Ab:%zz"),
+            basic_auth("a:b", Some("%zz"))
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use alloc::{boxed::Box, sync::Arc};
+#[cfg(feature = "std")]
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+#[cfg(feature = "std")]
+use std::sync::Mutex;
+
+#[cfg(feature = "std")]
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// The body of a [`Request`](crate::Request).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) enum Body {
+    /// A body that's already in memory.
+    Bytes(Vec<u8>),
+    /// A body that's read and sent piece by piece.
+    #[cfg(feature = "std")]
+    Reader(BodyReader),
+}
+
+impl Body {
+    /// Returns the length of the body, if it's known without reading
+    /// it.
+    #[cfg(feature = "std")]
+    pub(crate) fn known_length(&self) -> Option<u64> {
+        match self {
+            Body::Bytes(bytes) => Some(bytes.len() as u64),
+            #[cfg(feature = "std")]
+            Body::Reader(_) => None,
+        }
+    }
+
+    /// Returns true if the body can still be sent, ie. it isn't a
+    /// reader that has already been read.
+    #[cfg(feature = "std")]
+    pub(crate) fn is_replayable(&self) -> bool {
+        match self {
+            Body::Bytes(_) => true,
+            Body::Reader(reader) => !reader.is_consumed(),
+        }
+    }
+}
+
+/// A reader that can only be read once, shared between clones of the
+/// request it belongs to.
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub(crate) struct BodyReader(Arc<Mutex<Option<Box<dyn Read + Send>>>>);
+
+#[cfg(feature = "std")]
+impl BodyReader {
+    pub(crate) fn new<R: Read + Send + 'static>(reader: R) -> BodyReader {
+        BodyReader(Arc::new(Mutex::new(Some(Box::new(reader)))))
+    }
+
+    /// Takes the reader out, leaving this body consumed.
+    pub(crate) fn take(&self) -> Option<Box<dyn Read + Send>> {
+        self.0.lock().unwrap_or_else(|err| err.into_inner()).take()
+    }
+
+    fn is_consumed(&self) -> bool {
+        self.0
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .is_none()
+    }
+}
+
+#[cfg(feature = "std")]
+impl PartialEq for BodyReader {
+    fn eq(&self, other: &BodyReader) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Eq for BodyReader {}
+
+#[cfg(feature = "std")]
+impl fmt::Debug for BodyReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("BodyReader").finish()
+    }
+}
+
+/// Copies exactly `length` bytes from `reader` to `writer`, failing if
+/// the reader ends early.
+#[cfg(feature = "std")]
+pub(crate) fn write_sized<R: Read + ?Sized, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    length: u64,
+) -> io::Result<()> {
+    let written = io::copy(&mut reader.take(length), writer)?;
+    if written < length {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the request body ended before its Content-Length was reached",
+        ));
+    }
+    Ok(())
+}
+
+/// Copies everything from `reader` to `writer`, with `Transfer-Encoding:
+/// chunked`. See [RFC 7230 section
+/// 4.1](https://datatracker.ietf.org/doc/html/rfc7230#section-4.1).
+#[cfg(feature = "std")]
+pub(crate) fn write_chunked<R: Read + ?Sized, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+) -> io::Result<()> {
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let length = match reader.read(&mut buf) {
+            Ok(length) => length,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        if length == 0 {
+            break;
+        }
+        write!(writer, "{:X}\r\n", length)?;
+        writer.write_all(&buf[..length])?;
+        writer.write_all(b"\r\n")?;
+    }
+    writer.write_all(b"0\r\n\r\n")
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::{write_chunked, write_sized};
+
+    #[test]
+    fn chunked() {
+        let mut out = Vec::new();
+        write_chunked(&mut &b"hello world"[..], &mut out).unwrap();
+        assert_eq!(out, b"B\r\nhello world\r\n0\r\n\r\n");
+
+        let mut out = Vec::new();
+        write_chunked(&mut &b""[..], &mut out).unwrap();
+        assert_eq!(out, b"0\r\n\r\n");
+    }
+
+    #[test]
+    fn sized() {
+        let mut out = Vec::new();
+        write_sized(&mut &b"hello world"[..], &mut out, 5).unwrap();
+        assert_eq!(out, b"hello");
+
+        let mut out = Vec::new();
+        assert!(write_sized(&mut &b"hi"[..], &mut out, 5).is_err());
+    }
+}
@@ -0,0 +1,896 @@
+use crate::connection::Connection;
+use crate::date::parse_http_date;
+use crate::http_url::HttpUrl;
+use crate::request::ParsedRequest;
+use crate::{Error, HeaderMap, Method, Response};
+use alloc::sync::Arc;
+use core::fmt;
+use core::time::Duration;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::{TryFrom, TryInto};
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// The status codes whose responses can be cached without explicit
+/// freshness information, see RFC 9110 section 15.1.
+const HEURISTICALLY_CACHEABLE: [i32; 12] =
+    [200, 203, 204, 206, 300, 301, 308, 404, 405, 410, 414, 501];
+
+/// An HTTP cache, as described in [RFC
+/// 9111](https://datatracker.ietf.org/doc/html/rfc9111), that
+/// [`Request::send`](struct.Request.html#method.send) consults before
+/// sending a request. Set with
+/// [`Request::with_cache`](struct.Request.html#method.with_cache).
+///
+/// - Responses to `GET` requests are stored unless they have
+///   `Cache-Control: no-store`, or a `Vary: *` header, or were
+///   redirected. They are stored under the request's URL, along with
+///   the values of the request headers listed in their `Vary` header,
+///   which must match for the response to be reused.
+/// - A stored response is returned without contacting the server while
+///   it's fresh: until it's older than its `Cache-Control: max-age`, or
+///   its `Expires` date, taking its `Age` header into account. Without
+///   either, a response with a `Last-Modified` date is considered fresh
+///   for a tenth of the time since it was last modified.
+/// - Stale responses, and responses with `Cache-Control: no-cache`, are
+///   revalidated with `If-None-Match` and `If-Modified-Since` if they
+///   have an `ETag` or `Last-Modified` header. If the server answers
+///   with `304 Not Modified`, the stored response is updated with its
+///   headers and returned in its place.
+/// - Successful `POST`, `PUT`, `DELETE` and `PATCH` requests remove
+///   the stored response for their URL.
+/// - `Cache-Control: no-store` and `no-cache` in the request skip the
+///   cache, or force revalidation, respectively. Requests that already
+///   have conditional headers skip the cache too.
+///
+/// The cache is private by default, ie. meant for a single user, so it
+/// stores `Cache-Control: private` responses. See
+/// [`with_shared`](#method.with_shared) otherwise.
+///
+/// Responses served from the cache have an `Age` header, and their
+/// [`attempts`](struct.Response.html#method.attempts) is 0. The cache
+/// isn't used by [`send_lazy`](struct.Request.html#method.send_lazy).
+///
+/// The cache is cheap to clone, and all clones share the same store.
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> Result<(), minireq::Error> {
+/// use minireq::{Cache, MemoryStore};
+///
+/// let cache = Cache::new(MemoryStore::new(100));
+/// for _ in 0..10 {
+///     // Only sent to the server when the cached response is stale.
+///     let response = minireq::get("http://example.com/feed.xml")
+///         .with_cache(cache.clone())
+///         .send()?;
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Clone)]
+pub struct Cache {
+    store: Arc<dyn CacheStore>,
+    shared: bool,
+}
+
+impl Cache {
+    /// Creates a private cache that keeps its responses in `store`.
+    pub fn new<S: CacheStore + 'static>(store: S) -> Cache {
+        Cache {
+            store: Arc::new(store),
+            shared: false,
+        }
+    }
+
+    /// Sets whether the cache is shared between users, like a proxy's.
+    /// A shared cache doesn't store responses with `Cache-Control:
+    /// private`, or responses to requests with an `Authorization`
+    /// header unless the response allows it, and uses `s-maxage` over
+    /// `max-age`. Disabled by default.
+    pub fn with_shared(mut self, shared: bool) -> Cache {
+        self.shared = shared;
+        self
+    }
+
+    /// Sends the request, or answers it from the cache.
+    pub(crate) fn send(&self, mut request: ParsedRequest) -> Result<Response, Error> {
+        let key = cache_key(&Method::Get, &request.url);
+        let method = request.config.method.clone();
+        if method != Method::Get {
+            let response = fetch(request)?;
+            let unsafe_method = matches!(
+                method,
+                Method::Post | Method::Put | Method::Delete | Method::Patch
+            );
+            if unsafe_method && response.status_code < 400 {
+                self.store.remove(&key);
+            }
+            return Ok(response);
+        }
+
+        // Vary is matched against the headers as sent, including the
+        // ones added by us, like Accept-Encoding and the jar's cookies.
+        let request_headers = request.wire_headers();
+        let request_directives = CacheControl::parse(&request_headers);
+        let conditional = ["if-none-match", "if-modified-since", "if-match", "if-range"]
+            .iter()
+            .any(|name| request_headers.contains_key(name));
+        if request_directives.no_store || conditional {
+            return fetch(request);
+        }
+
+        let entry = (self.store.get(&key)).filter(|entry| entry.matches_vary(&request_headers));
+        let mut validated = None;
+        if let Some(entry) = entry {
+            let now = SystemTime::now();
+            if !request_directives.no_cache && entry.is_fresh(self.shared, now) {
+                #[cfg(feature = "log")]
+                log::debug!("Serving {} from the cache.", key);
+                return Ok(entry.to_response(now, 0));
+            }
+            if let Some(etag) = entry.headers.get("etag") {
+                request.config.headers.insert("If-None-Match", etag);
+            }
+            if let Some(last_modified) = entry.headers.get("last-modified") {
+                request
+                    .config
+                    .headers
+                    .insert("If-Modified-Since", last_modified);
+            }
+            if entry.headers.contains_key("etag") || entry.headers.contains_key("last-modified") {
+                validated = Some(entry);
+            }
+        }
+
+        let request_time = SystemTime::now();
+        let response = fetch(request)?;
+        let response_time = SystemTime::now();
+        if let Some(mut entry) = validated {
+            if response.status_code == 304 {
+                #[cfg(feature = "log")]
+                log::debug!("Revalidated the cached response for {}.", key);
+                entry.update(&response.headers, request_time, response_time);
+                self.store.put(&key, &entry);
+                return Ok(entry.to_response(response_time, response.attempts()));
+            }
+        }
+
+        if self.is_storable(&request_headers, &response) {
+            let entry = CacheEntry::new(&request_headers, &response, request_time, response_time);
+            self.store.put(&key, &entry);
+        } else {
+            self.store.remove(&key);
+        }
+        Ok(response)
+    }
+
+    /// Returns true if the response to a `GET` request can be stored,
+    /// see RFC 9111 section 3.
+    fn is_storable(&self, request_headers: &HeaderMap, response: &Response) -> bool {
+        let directives = CacheControl::parse(&response.headers);
+        let vary_all = (response.headers.get_all("vary"))
+            .flat_map(|vary| vary.split(','))
+            .any(|name| name.trim() == "*");
+        if directives.no_store
+            || vary_all
+            || matches!(response.status_code, 206 | 304)
+            || !response.redirect_history().is_empty()
+        {
+            return false;
+        }
+        if self.shared {
+            let authorized = request_headers.contains_key("authorization")
+                && !(directives.public
+                    || directives.must_revalidate
+                    || directives.s_maxage.is_some());
+            if directives.private || authorized {
+                return false;
+            }
+        }
+        directives.public
+            || directives.max_age.is_some()
+            || (self.shared && directives.s_maxage.is_some())
+            || response.headers.contains_key("expires")
+            || HEURISTICALLY_CACHEABLE.contains(&response.status_code)
+    }
+}
+
+impl PartialEq for Cache {
+    fn eq(&self, other: &Cache) -> bool {
+        let same_store =
+            Arc::as_ptr(&self.store) as *const () == Arc::as_ptr(&other.store) as *const ();
+        same_store && self.shared == other.shared
+    }
+}
+
+impl Eq for Cache {}
+
+impl fmt::Debug for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("shared", &self.shared)
+            .finish()
+    }
+}
+
+/// Storage for the responses of a [`Cache`].
+///
+/// [`MemoryStore`] and [`DiskStore`] are provided, other backends can
+/// implement this trait, using [`CacheEntry::to_bytes`] and
+/// [`CacheEntry::from_bytes`] to serialize the entries. Failing to
+/// store an entry isn't an error, the response is just fetched again
+/// the next time.
+pub trait CacheStore: Send + Sync {
+    /// Returns the entry stored under `key`, if there is one.
+    fn get(&self, key: &str) -> Option<CacheEntry>;
+    /// Stores `entry` under `key`, replacing any previous entry.
+    fn put(&self, key: &str, entry: &CacheEntry);
+    /// Removes the entry stored under `key`, if there is one.
+    fn remove(&self, key: &str);
+}
+
+/// A response stored in a [`Cache`], along with what's needed to tell
+/// how old it is, and which requests it can be reused for.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CacheEntry {
+    status_code: i32,
+    reason_phrase: String,
+    headers: HeaderMap,
+    url: String,
+    body: Vec<u8>,
+    request_time: SystemTime,
+    response_time: SystemTime,
+    /// The request's values for the headers named in the response's
+    /// `Vary` header.
+    vary: Vec<(String, Option<String>)>,
+}
+
+const ENTRY_FORMAT_VERSION: &[u8] = b"minireq-cache-1";
+
+impl CacheEntry {
+    fn new(
+        request_headers: &HeaderMap,
+        response: &Response,
+        request_time: SystemTime,
+        response_time: SystemTime,
+    ) -> CacheEntry {
+        let vary = (response.headers.get_all("vary"))
+            .flat_map(|vary| vary.split(','))
+            .map(|name| name.trim().to_ascii_lowercase())
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                let value = request_headers.get(&name).map(str::to_string);
+                (name, value)
+            })
+            .collect();
+        CacheEntry {
+            status_code: response.status_code,
+            reason_phrase: response.reason_phrase.clone(),
+            headers: response.headers.clone(),
+            url: response.url.clone(),
+            body: response.as_bytes().to_vec(),
+            request_time,
+            response_time,
+            vary,
+        }
+    }
+
+    /// Returns the status code of the stored response.
+    pub fn status_code(&self) -> i32 {
+        self.status_code
+    }
+
+    /// Returns the URL of the stored response.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns the headers of the stored response.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Returns the body of the stored response.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Serializes the entry, to be read back with
+    /// [`from_bytes`](#method.from_bytes).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.body.len() + 256);
+        put_bytes(&mut bytes, ENTRY_FORMAT_VERSION);
+        put_u64(&mut bytes, self.status_code as u64);
+        put_bytes(&mut bytes, self.reason_phrase.as_bytes());
+        put_bytes(&mut bytes, self.url.as_bytes());
+        put_u64(&mut bytes, unix_millis(self.request_time));
+        put_u64(&mut bytes, unix_millis(self.response_time));
+        put_u64(&mut bytes, self.headers.len() as u64);
+        for (name, value) in &self.headers {
+            put_bytes(&mut bytes, name.as_bytes());
+            put_bytes(&mut bytes, value.as_bytes());
+        }
+        put_u64(&mut bytes, self.vary.len() as u64);
+        for (name, value) in &self.vary {
+            put_bytes(&mut bytes, name.as_bytes());
+            put_u64(&mut bytes, value.is_some() as u64);
+            put_bytes(&mut bytes, value.as_deref().unwrap_or("").as_bytes());
+        }
+        put_bytes(&mut bytes, &self.body);
+        bytes
+    }
+
+    /// Deserializes an entry written by [`to_bytes`](#method.to_bytes).
+    /// Returns None if `bytes` isn't one, eg. because it was written by
+    /// an incompatible version of this crate.
+    pub fn from_bytes(bytes: &[u8]) -> Option<CacheEntry> {
+        let mut reader = EntryReader(bytes);
+        if reader.bytes()? != ENTRY_FORMAT_VERSION {
+            return None;
+        }
+        let status_code = i32::try_from(reader.u64()?).ok()?;
+        let reason_phrase = reader.string()?;
+        let url = reader.string()?;
+        let request_time = from_unix_millis(reader.u64()?);
+        let response_time = from_unix_millis(reader.u64()?);
+        let mut headers = HeaderMap::new();
+        for _ in 0..reader.u64()? {
+            let name = reader.string()?;
+            headers.append(name, reader.string()?);
+        }
+        let mut vary = Vec::new();
+        for _ in 0..reader.u64()? {
+            let name = reader.string()?;
+            let present = reader.u64()? == 1;
+            let value = reader.string()?;
+            vary.push((name, Some(value).filter(|_| present)));
+        }
+        let body = reader.bytes()?.to_vec();
+        if !reader.0.is_empty() {
+            return None;
+        }
+        Some(CacheEntry {
+            status_code,
+            reason_phrase,
+            headers,
+            url,
+            body,
+            request_time,
+            response_time,
+            vary,
+        })
+    }
+
+    fn matches_vary(&self, request_headers: &HeaderMap) -> bool {
+        (self.vary.iter()).all(|(name, value)| request_headers.get(name) == value.as_deref())
+    }
+
+    /// Returns true if the entry can be returned without revalidating
+    /// it, see RFC 9111 section 4.2.
+    fn is_fresh(&self, shared: bool, now: SystemTime) -> bool {
+        let directives = CacheControl::parse(&self.headers);
+        !directives.no_cache && self.freshness_lifetime(shared) > self.current_age(now)
+    }
+
+    fn freshness_lifetime(&self, shared: bool) -> Duration {
+        let directives = CacheControl::parse(&self.headers);
+        if let Some(s_maxage) = directives.s_maxage.filter(|_| shared) {
+            return s_maxage;
+        }
+        if let Some(max_age) = directives.max_age {
+            return max_age;
+        }
+        let date = self.date();
+        if let Some(expires) = self.headers.get("expires") {
+            // Invalid dates, like "0", mean the response has already
+            // expired.
+            return (parse_http_date(expires))
+                .and_then(|expires| expires.duration_since(date).ok())
+                .unwrap_or(Duration::ZERO);
+        }
+        // Without explicit freshness information, 10% of the time
+        // since the resource was last changed is a common heuristic.
+        match self.headers.get("last-modified").and_then(parse_http_date) {
+            Some(last_modified) if HEURISTICALLY_CACHEABLE.contains(&self.status_code) => {
+                date.duration_since(last_modified).unwrap_or(Duration::ZERO) / 10
+            }
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Returns the age of the response, see RFC 9111 section 4.2.3.
+    fn current_age(&self, now: SystemTime) -> Duration {
+        let age_value = (self.headers.get("age"))
+            .and_then(|age| age.trim().parse().ok())
+            .map_or(Duration::ZERO, Duration::from_secs);
+        let apparent_age =
+            (self.response_time.duration_since(self.date())).unwrap_or(Duration::ZERO);
+        let response_delay =
+            (self.response_time.duration_since(self.request_time)).unwrap_or(Duration::ZERO);
+        let corrected_initial_age = apparent_age.max(age_value + response_delay);
+        let resident_time = now
+            .duration_since(self.response_time)
+            .unwrap_or(Duration::ZERO);
+        corrected_initial_age + resident_time
+    }
+
+    fn date(&self) -> SystemTime {
+        (self.headers.get("date"))
+            .and_then(parse_http_date)
+            .unwrap_or(self.response_time)
+    }
+
+    /// Replaces the stored headers with the ones from a `304 Not
+    /// Modified` response, see RFC 9111 section 3.2.
+    fn update(&mut self, headers: &HeaderMap, request_time: SystemTime, response_time: SystemTime) {
+        for (name, _) in headers {
+            if !is_framing_header(name) {
+                self.headers.remove(name);
+            }
+        }
+        for (name, value) in headers {
+            if !is_framing_header(name) {
+                self.headers.append(name, value);
+            }
+        }
+        self.request_time = request_time;
+        self.response_time = response_time;
+    }
+
+    fn to_response(&self, now: SystemTime, attempts: u32) -> Response {
+        let mut headers = self.headers.clone();
+        headers.insert("age", self.current_age(now).as_secs().to_string());
+        Response::from_cache(
+            self.status_code,
+            self.reason_phrase.clone(),
+            headers,
+            self.url.clone(),
+            self.body.clone(),
+            attempts,
+        )
+    }
+}
+
+fn is_framing_header(name: &str) -> bool {
+    ["content-length", "content-encoding", "transfer-encoding"]
+        .iter()
+        .any(|framing| name.eq_ignore_ascii_case(framing))
+}
+
+/// A [`CacheStore`] that keeps up to a set amount of entries in
+/// memory, evicting the least recently used one when it's full.
+pub struct MemoryStore {
+    inner: Mutex<MemoryStoreInner>,
+}
+
+struct MemoryStoreInner {
+    /// The entries, along with when they were last used.
+    entries: HashMap<String, (u64, CacheEntry)>,
+    /// The keys of the entries, by when they were last used.
+    recently_used: BTreeMap<u64, String>,
+    clock: u64,
+    max_entries: usize,
+}
+
+impl MemoryStore {
+    /// Creates a store that holds at most `max_entries` responses.
+    pub fn new(max_entries: usize) -> MemoryStore {
+        MemoryStore {
+            inner: Mutex::new(MemoryStoreInner {
+                entries: HashMap::new(),
+                recently_used: BTreeMap::new(),
+                clock: 0,
+                max_entries,
+            }),
+        }
+    }
+
+    /// Returns the amount of responses in the store.
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Returns true if the store is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MemoryStoreInner> {
+        // Every change leaves the two maps consistent before anything
+        // that could panic.
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl MemoryStoreInner {
+    /// Marks the entry as the most recently used one.
+    fn touch(&mut self, key: &str) {
+        self.clock += 1;
+        let clock = self.clock;
+        if let Some((last_used, _)) = self.entries.get_mut(key) {
+            self.recently_used.remove(last_used);
+            *last_used = clock;
+            self.recently_used.insert(clock, key.to_string());
+        }
+    }
+}
+
+impl CacheStore for MemoryStore {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        let mut inner = self.lock();
+        inner.touch(key);
+        inner.entries.get(key).map(|(_, entry)| entry.clone())
+    }
+
+    fn put(&self, key: &str, entry: &CacheEntry) {
+        let mut inner = self.lock();
+        if inner.max_entries == 0 {
+            return;
+        }
+        if let Some((last_used, _)) = inner.entries.insert(key.to_string(), (0, entry.clone())) {
+            inner.recently_used.remove(&last_used);
+        }
+        inner.touch(key);
+        while inner.entries.len() > inner.max_entries {
+            let last_used = *inner.recently_used.keys().next().unwrap();
+            let oldest = inner.recently_used.remove(&last_used).unwrap();
+            inner.entries.remove(&oldest);
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        let mut inner = self.lock();
+        if let Some((last_used, _)) = inner.entries.remove(key) {
+            inner.recently_used.remove(&last_used);
+        }
+    }
+}
+
+impl fmt::Debug for MemoryStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inner = self.lock();
+        f.debug_struct("MemoryStore")
+            .field("entries", &inner.entries.len())
+            .field("max_entries", &inner.max_entries)
+            .finish()
+    }
+}
+
+/// A [`CacheStore`] that keeps each entry in a file in a directory, so
+/// that the cache outlives the process. The directory isn't limited in
+/// size, entries are only removed when they're replaced or invalidated.
+#[derive(Debug)]
+pub struct DiskStore {
+    dir: PathBuf,
+}
+
+impl DiskStore {
+    /// Creates a store that keeps its entries in `dir`, creating the
+    /// directory if it doesn't exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory couldn't be created.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Result<DiskStore, Error> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(DiskStore { dir })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir
+            .join(format!("{:016x}.entry", fnv1a(key.as_bytes())))
+    }
+
+    fn write(&self, key: &str, entry: &CacheEntry) -> io::Result<()> {
+        static TEMP_FILES: AtomicU64 = AtomicU64::new(0);
+        let path = self.path(key);
+        // Written to a temporary file first, so that other threads and
+        // processes never read a partially written entry.
+        let temp_path = path.with_extension(format!(
+            "{}-{}.tmp",
+            std::process::id(),
+            TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut file = fs::File::create(&temp_path)?;
+        let mut contents = Vec::new();
+        put_bytes(&mut contents, key.as_bytes());
+        contents.extend(entry.to_bytes());
+        let written = file
+            .write_all(&contents)
+            .and_then(|_| fs::rename(&temp_path, &path));
+        if written.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        written
+    }
+}
+
+impl CacheStore for DiskStore {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        let contents = fs::read(self.path(key)).ok()?;
+        let mut reader = EntryReader(&contents);
+        // Different keys can have the same file name.
+        if reader.bytes()? != key.as_bytes() {
+            return None;
+        }
+        CacheEntry::from_bytes(reader.0)
+    }
+
+    fn put(&self, key: &str, entry: &CacheEntry) {
+        if let Err(_err) = self.write(key, entry) {
+            #[cfg(feature = "log")]
+            log::debug!("Could not write the cache entry for {}: {}", key, _err);
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        let _ = fs::remove_file(self.path(key));
+    }
+}
+
+/// The `Cache-Control` directives this cache understands.
+#[derive(Default)]
+struct CacheControl {
+    no_store: bool,
+    no_cache: bool,
+    private: bool,
+    public: bool,
+    must_revalidate: bool,
+    max_age: Option<Duration>,
+    s_maxage: Option<Duration>,
+}
+
+impl CacheControl {
+    fn parse(headers: &HeaderMap) -> CacheControl {
+        let mut directives = CacheControl::default();
+        for directive in headers
+            .get_all("cache-control")
+            .flat_map(|value| value.split(','))
+        {
+            let (name, argument) = match directive.split_once('=') {
+                Some((name, argument)) => (name.trim(), Some(argument.trim().trim_matches('"'))),
+                None => (directive.trim(), None),
+            };
+            let seconds =
+                || (argument.and_then(|seconds| seconds.parse().ok())).map(Duration::from_secs);
+            match name.to_ascii_lowercase().as_str() {
+                "no-store" => directives.no_store = true,
+                // The forms with a list of header names are treated like
+                // the plain ones, which is stricter.
+                "no-cache" => directives.no_cache = true,
+                "private" => directives.private = true,
+                "public" => directives.public = true,
+                "must-revalidate" | "proxy-revalidate" => directives.must_revalidate = true,
+                // Invalid ages mean the response is stale.
+                "max-age" => directives.max_age = Some(seconds().unwrap_or(Duration::ZERO)),
+                "s-maxage" => directives.s_maxage = Some(seconds().unwrap_or(Duration::ZERO)),
+                _ => {}
+            }
+        }
+        directives
+    }
+}
+
+fn fetch(request: ParsedRequest) -> Result<Response, Error> {
+    Response::create(Connection::new(request).send()?)
+}
+
+/// Returns the key of the responses to `method` requests for `url` in
+/// the store: the method and the URL, without the fragment.
+fn cache_key(method: &Method, url: &HttpUrl) -> String {
+    let mut key = format!("{} ", method);
+    url.write_base_url_to(&mut key).unwrap();
+    key.push_str(&url.path_and_query);
+    key
+}
+
+fn put_u64(bytes: &mut Vec<u8>, value: u64) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_bytes(bytes: &mut Vec<u8>, value: &[u8]) {
+    put_u64(bytes, value.len() as u64);
+    bytes.extend_from_slice(value);
+}
+
+/// Reads the values written by `put_u64` and `put_bytes`.
+struct EntryReader<'a>(&'a [u8]);
+
+impl<'a> EntryReader<'a> {
+    fn u64(&mut self) -> Option<u64> {
+        let value = self.take(8)?;
+        Some(u64::from_le_bytes(value.try_into().ok()?))
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let length = usize::try_from(self.u64()?).ok()?;
+        self.take(length)
+    }
+
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        if self.0.len() < length {
+            return None;
+        }
+        let (value, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Some(value)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        String::from_utf8(self.bytes()?.to_vec()).ok()
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    let since_epoch = time.duration_since(SystemTime::UNIX_EPOCH);
+    since_epoch.map_or(0, |duration| duration.as_millis() as u64)
+}
+
+fn from_unix_millis(millis: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_millis(millis)
+}
+
+/// The 64-bit FNV-1a hash, which unlike the standard library's hashers
+/// is guaranteed to stay the same between versions, so file names
+/// stay the same too.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CacheControl, CacheEntry, CacheStore, DiskStore, MemoryStore};
+    use crate::HeaderMap;
+    use core::time::Duration;
+    use std::time::SystemTime;
+
+    fn entry(headers: &[(&str, &str)], response_time: SystemTime) -> CacheEntry {
+        CacheEntry {
+            status_code: 200,
+            reason_phrase: "OK".to_string(),
+            headers: headers.iter().copied().collect(),
+            url: "http://example.com/".to_string(),
+            body: b"body".to_vec(),
+            request_time: response_time,
+            response_time,
+            vary: vec![("accept".to_string(), Some("text/html".to_string()))],
+        }
+    }
+
+    #[test]
+    fn directives() {
+        let headers = [(
+            "Cache-Control",
+            "no-cache=\"Set-Cookie\", max-age=\"60\", S-MAXAGE=invalid",
+        )]
+        .iter()
+        .copied()
+        .collect::<HeaderMap>();
+        let directives = CacheControl::parse(&headers);
+        assert!(directives.no_cache && !directives.no_store);
+        assert_eq!(directives.max_age, Some(Duration::from_secs(60)));
+        assert_eq!(directives.s_maxage, Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn freshness() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        let minutes = |minutes: u64| now - Duration::from_secs(minutes * 60);
+
+        let max_age = entry(&[("cache-control", "max-age=600")], minutes(5));
+        assert!(max_age.is_fresh(false, now));
+        assert!(!max_age.is_fresh(false, now + Duration::from_secs(300)));
+
+        // The Age header counts towards the age.
+        let aged = entry(
+            &[("cache-control", "max-age=600"), ("age", "400")],
+            minutes(5),
+        );
+        assert!(!aged.is_fresh(false, now));
+
+        // s-maxage only applies to shared caches.
+        let s_maxage = entry(&[("cache-control", "max-age=600, s-maxage=60")], minutes(5));
+        assert!(s_maxage.is_fresh(false, now));
+        assert!(!s_maxage.is_fresh(true, now));
+
+        let expires = entry(
+            &[
+                ("date", "Sun, 09 Sep 2001 01:40:00 GMT"),
+                ("expires", "Sun, 09 Sep 2001 01:50:00 GMT"),
+            ],
+            minutes(5),
+        );
+        assert!(expires.is_fresh(false, now));
+        let invalid_expires = entry(&[("expires", "0")], minutes(5));
+        assert!(!invalid_expires.is_fresh(false, now));
+
+        // Heuristic freshness: 10% of the 100 minutes since the last
+        // modification.
+        let heuristic = entry(
+            &[
+                ("date", "Sun, 09 Sep 2001 01:40:00 GMT"),
+                ("last-modified", "Sun, 09 Sep 2001 00:00:00 GMT"),
+            ],
+            minutes(5),
+        );
+        assert!(heuristic.is_fresh(false, now));
+        assert!(!heuristic.is_fresh(false, now + Duration::from_secs(300)));
+
+        let no_cache = entry(&[("cache-control", "max-age=600, no-cache")], now);
+        assert!(!no_cache.is_fresh(false, now));
+    }
+
+    #[test]
+    fn revalidation_updates_headers() {
+        let now = SystemTime::now();
+        let mut stale = entry(
+            &[("ETag", "\"1\""), ("Content-Length", "4"), ("X-Kept", "1")],
+            now,
+        );
+        let not_modified = [("etag", "\"2\""), ("content-length", "0")]
+            .iter()
+            .copied()
+            .collect::<HeaderMap>();
+        stale.update(&not_modified, now, now);
+        assert_eq!(stale.headers.get("etag"), Some("\"2\""));
+        assert_eq!(stale.headers.get("content-length"), Some("4"));
+        assert_eq!(stale.headers.get("x-kept"), Some("1"));
+    }
+
+    #[test]
+    fn serialization() {
+        let entry = entry(
+            &[("ETag", "\"1\""), ("Set-Cookie", "a=1")],
+            SystemTime::now(),
+        );
+        let bytes = entry.to_bytes();
+        let mut deserialized = CacheEntry::from_bytes(&bytes).unwrap();
+        // Times are stored in milliseconds.
+        deserialized.request_time = entry.request_time;
+        deserialized.response_time = entry.response_time;
+        assert_eq!(deserialized, entry);
+
+        assert_eq!(CacheEntry::from_bytes(&bytes[..bytes.len() - 1]), None);
+        assert_eq!(CacheEntry::from_bytes(b"not an entry"), None);
+    }
+
+    #[test]
+    fn memory_store_evicts_least_recently_used() {
+        let store = MemoryStore::new(2);
+        let entry = entry(&[], SystemTime::now());
+        store.put("a", &entry);
+        store.put("b", &entry);
+        assert!(store.get("a").is_some());
+        store.put("c", &entry);
+        assert_eq!(store.len(), 2);
+        assert!(store.get("b").is_none());
+        assert!(store.get("a").is_some() && store.get("c").is_some());
+        store.remove("a");
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn disk_store() {
+        let dir = std::env::temp_dir().join(format!("minireq-cache-test-{}", std::process::id()));
+        let store = DiskStore::new(&dir).unwrap();
+        let entry = entry(&[("ETag", "\"1\"")], SystemTime::UNIX_EPOCH);
+        store.put("GET http://example.com/", &entry);
+        assert_eq!(store.get("GET http://example.com/"), Some(entry.clone()));
+        assert_eq!(store.get("GET http://example.com/other"), None);
+
+        // Entries outlive the store.
+        let reopened = DiskStore::new(&dir).unwrap();
+        assert_eq!(reopened.get("GET http://example.com/"), Some(entry));
+        reopened.remove("GET http://example.com/");
+        assert_eq!(store.get("GET http://example.com/"), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
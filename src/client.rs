@@ -0,0 +1,375 @@
+use crate::cache::Cache;
+use crate::cookie::CookieJar;
+use crate::pool::ConnectionPool;
+#[cfg(feature = "proxy")]
+use crate::proxy::Proxy;
+use crate::redirect::RedirectPolicy;
+use crate::request::{Method, Request, URL};
+use crate::retry::RetryPolicy;
+use core::time::Duration;
+
+/// A set of defaults shared by many requests.
+///
+/// Requests created through a client (eg. with
+/// [`Client::get`](#method.get)) start out with the client's headers,
+/// timeout, limits, proxy and TLS configuration, which can then be
+/// overridden per request with the usual
+/// [`Request`](struct.Request.html) methods. If the client has a base
+/// URL, relative URLs are resolved against it.
+///
+/// Every client owns a [`ConnectionPool`], so requests made through
+/// the same client (or its clones) reuse connections.
+///
+/// Clients are cheap to clone, and can be shared between threads.
+///
+/// # Example
+///
+/// ```no_run
+/// # #[cfg(feature = "std")]
+/// # fn main() -> Result<(), minireq::Error> {
+/// let client = minireq::Client::new()
+///     .with_base_url("http://example.com/api")
+///     .with_header("Accept", "application/json")
+///     .with_timeout(10);
+///
+/// // Sends a GET request to http://example.com/api/users.
+/// let users = client.get("/users").send()?;
+/// // Overrides the client's timeout for this request only.
+/// let report = client.get("/report").with_timeout(60).send()?;
+/// # Ok(()) }
+/// # #[cfg(not(feature = "std"))]
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> { Ok(()) }
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Client {
+    base_url: Option<URL>,
+    defaults: Request,
+}
+
+impl Client {
+    /// Creates a new `Client` with no base URL, no default headers,
+    /// and a new connection pool.
+    pub fn new() -> Client {
+        Client {
+            base_url: None,
+            defaults: Request::new(Method::Get, "").with_pool(ConnectionPool::new()),
+        }
+    }
+
+    /// Sets the URL that relative request URLs are appended to.
+    ///
+    /// URLs that contain a scheme (eg. `https://`) are used as-is.
+    pub fn with_base_url<T: Into<URL>>(mut self, base_url: T) -> Client {
+        self.base_url = Some(base_url.into());
+        self
+    }
+
+    /// Adds headers that are sent with every request. See
+    /// [`Request::with_headers`](struct.Request.html#method.with_headers).
+    pub fn with_headers<T, K, V>(mut self, headers: T) -> Client
+    where
+        T: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.defaults = self.defaults.with_headers(headers);
+        self
+    }
+
+    /// Adds a header that is sent with every request. See
+    /// [`Request::with_header`](struct.Request.html#method.with_header).
+    pub fn with_header<T: Into<String>, U: Into<String>>(mut self, key: T, value: U) -> Client {
+        self.defaults = self.defaults.with_header(key, value);
+        self
+    }
+
+    /// Sets the `Basic` credentials sent with every request. See
+    /// [`Request::with_basic_auth`](struct.Request.html#method.with_basic_auth).
+    pub fn with_basic_auth<U: AsRef<str>>(mut self, username: U, password: Option<&str>) -> Client {
+        self.defaults = self.defaults.with_basic_auth(username, password);
+        self
+    }
+
+    /// Sets the `Bearer` token sent with every request. See
+    /// [`Request::with_bearer_auth`](struct.Request.html#method.with_bearer_auth).
+    pub fn with_bearer_auth<T: AsRef<str>>(mut self, token: T) -> Client {
+        self.defaults = self.defaults.with_bearer_auth(token);
+        self
+    }
+
+    /// Sets the `Digest` credentials used by every request. The
+    /// requests share the challenge they answer, so after the first
+    /// one, the `Authorization` header is sent up front. See
+    /// [`Request::with_digest_auth`](struct.Request.html#method.with_digest_auth).
+    #[cfg(feature = "digest-auth")]
+    pub fn with_digest_auth<U: Into<String>, P: Into<String>>(
+        mut self,
+        username: U,
+        password: P,
+    ) -> Client {
+        self.defaults = self.defaults.with_digest_auth(username, password);
+        self
+    }
+
+    /// Sets the default timeout in seconds. See
+    /// [`Request::with_timeout`](struct.Request.html#method.with_timeout).
+    pub fn with_timeout(mut self, timeout: u64) -> Client {
+        self.defaults = self.defaults.with_timeout(timeout);
+        self
+    }
+
+    /// Sets the default total timeout. See
+    /// [`Request::with_total_timeout`](struct.Request.html#method.with_total_timeout).
+    pub fn with_total_timeout(mut self, timeout: Duration) -> Client {
+        self.defaults = self.defaults.with_total_timeout(timeout);
+        self
+    }
+
+    /// Sets the default connect timeout. See
+    /// [`Request::with_connect_timeout`](struct.Request.html#method.with_connect_timeout).
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Client {
+        self.defaults = self.defaults.with_connect_timeout(timeout);
+        self
+    }
+
+    /// Sets the default TLS handshake timeout. See
+    /// [`Request::with_tls_handshake_timeout`](struct.Request.html#method.with_tls_handshake_timeout).
+    #[cfg(feature = "rustls")]
+    pub fn with_tls_handshake_timeout(mut self, timeout: Duration) -> Client {
+        self.defaults = self.defaults.with_tls_handshake_timeout(timeout);
+        self
+    }
+
+    /// Sets the default read timeout. See
+    /// [`Request::with_read_timeout`](struct.Request.html#method.with_read_timeout).
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Client {
+        self.defaults = self.defaults.with_read_timeout(timeout);
+        self
+    }
+
+    /// Sets the default write timeout. See
+    /// [`Request::with_write_timeout`](struct.Request.html#method.with_write_timeout).
+    pub fn with_write_timeout(mut self, timeout: Duration) -> Client {
+        self.defaults = self.defaults.with_write_timeout(timeout);
+        self
+    }
+
+    /// Sets the default max redirects. See
+    /// [`Request::with_max_redirects`](struct.Request.html#method.with_max_redirects).
+    pub fn with_max_redirects(mut self, max_redirects: usize) -> Client {
+        self.defaults = self.defaults.with_max_redirects(max_redirects);
+        self
+    }
+
+    /// Sets the default redirect policy. See
+    /// [`Request::with_redirect_policy`](struct.Request.html#method.with_redirect_policy).
+    pub fn with_redirect_policy(mut self, redirect_policy: RedirectPolicy) -> Client {
+        self.defaults = self.defaults.with_redirect_policy(redirect_policy);
+        self
+    }
+
+    /// Sets the default retry policy. See
+    /// [`Request::with_retry_policy`](struct.Request.html#method.with_retry_policy).
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Client {
+        self.defaults = self.defaults.with_retry_policy(retry_policy);
+        self
+    }
+
+    /// Sets the default maximum size of response headers. See
+    /// [`Request::with_max_headers_size`](struct.Request.html#method.with_max_headers_size).
+    pub fn with_max_headers_size<S: Into<Option<usize>>>(mut self, max_headers_size: S) -> Client {
+        self.defaults = self.defaults.with_max_headers_size(max_headers_size);
+        self
+    }
+
+    /// Sets the default maximum length of the response status line.
+    /// See
+    /// [`Request::with_max_status_line_length`](struct.Request.html#method.with_max_status_line_length).
+    pub fn with_max_status_line_length<S: Into<Option<usize>>>(
+        mut self,
+        max_status_line_len: S,
+    ) -> Client {
+        self.defaults = self
+            .defaults
+            .with_max_status_line_length(max_status_line_len);
+        self
+    }
+
+    /// Sets whether responses are decompressed by default. See
+    /// [`Request::with_decompression`](struct.Request.html#method.with_decompression).
+    pub fn with_decompression(mut self, decompress: bool) -> Client {
+        self.defaults = self.defaults.with_decompression(decompress);
+        self
+    }
+
+    /// Sets the proxy used for every request. See
+    /// [`Request::with_proxy`](struct.Request.html#method.with_proxy).
+    #[cfg(feature = "proxy")]
+    pub fn with_proxy(mut self, proxy: Proxy) -> Client {
+        self.defaults = self.defaults.with_proxy(proxy);
+        self
+    }
+
+    /// Sets whether the proxy is taken from the environment by default.
+    /// See [`Request::with_env_proxy`](struct.Request.html#method.with_env_proxy).
+    #[cfg(feature = "proxy")]
+    pub fn with_env_proxy(mut self, env_proxy: bool) -> Client {
+        self.defaults = self.defaults.with_env_proxy(env_proxy);
+        self
+    }
+
+    /// Sets the rustls configuration used for https requests. See
+    /// [`Request::with_tls_config`](struct.Request.html#method.with_tls_config).
+    #[cfg(feature = "rustls")]
+    pub fn with_tls_config(mut self, config: alloc::sync::Arc<rustls::ClientConfig>) -> Client {
+        self.defaults = self.defaults.with_tls_config(config);
+        self
+    }
+
+    /// Replaces the client's connection pool, eg. to share one pool
+    /// between several clients.
+    pub fn with_pool(mut self, pool: ConnectionPool) -> Client {
+        self.defaults = self.defaults.with_pool(pool);
+        self
+    }
+
+    /// Returns the connection pool used by requests made through this
+    /// client.
+    pub fn pool(&self) -> &ConnectionPool {
+        // Set in Client::new, and can only be replaced afterwards.
+        self.defaults.pool.as_ref().unwrap()
+    }
+
+    /// Sets the cache used by every request. See
+    /// [`Request::with_cache`](struct.Request.html#method.with_cache).
+    pub fn with_cache(mut self, cache: Cache) -> Client {
+        self.defaults = self.defaults.with_cache(cache);
+        self
+    }
+
+    /// Sets the cookie jar used by every request, so that cookies set
+    /// by one response are sent with the following requests. See
+    /// [`Request::with_cookie_jar`](struct.Request.html#method.with_cookie_jar).
+    pub fn with_cookie_jar(mut self, cookie_jar: CookieJar) -> Client {
+        self.defaults = self.defaults.with_cookie_jar(cookie_jar);
+        self
+    }
+
+    /// Creates a new `Request` with the client's defaults. `url` is
+    /// resolved against the base URL, if there is one.
+    pub fn request<T: Into<URL>>(&self, method: Method, url: T) -> Request {
+        let mut request = self.defaults.clone();
+        request.method = method;
+        request.url = self.resolve(url.into());
+        request
+    }
+
+    fn resolve(&self, url: URL) -> URL {
+        match self.base_url {
+            Some(ref base_url) if !url.contains("://") => {
+                let base_url = base_url.trim_end_matches('/');
+                if url.is_empty() {
+                    base_url.to_string()
+                } else if url.starts_with('/') {
+                    format!("{}{}", base_url, url)
+                } else {
+                    format!("{}/{}", base_url, url)
+                }
+            }
+            _ => url,
+        }
+    }
+
+    /// Alias for [Client::request](#method.request) with `method` set
+    /// to [Method::Get](enum.Method.html).
+    pub fn get<T: Into<URL>>(&self, url: T) -> Request {
+        self.request(Method::Get, url)
+    }
+
+    /// Alias for [Client::request](#method.request) with `method` set
+    /// to [Method::Head](enum.Method.html).
+    pub fn head<T: Into<URL>>(&self, url: T) -> Request {
+        self.request(Method::Head, url)
+    }
+
+    /// Alias for [Client::request](#method.request) with `method` set
+    /// to [Method::Post](enum.Method.html).
+    pub fn post<T: Into<URL>>(&self, url: T) -> Request {
+        self.request(Method::Post, url)
+    }
+
+    /// Alias for [Client::request](#method.request) with `method` set
+    /// to [Method::Put](enum.Method.html).
+    pub fn put<T: Into<URL>>(&self, url: T) -> Request {
+        self.request(Method::Put, url)
+    }
+
+    /// Alias for [Client::request](#method.request) with `method` set
+    /// to [Method::Delete](enum.Method.html).
+    pub fn delete<T: Into<URL>>(&self, url: T) -> Request {
+        self.request(Method::Delete, url)
+    }
+
+    /// Alias for [Client::request](#method.request) with `method` set
+    /// to [Method::Options](enum.Method.html).
+    pub fn options<T: Into<URL>>(&self, url: T) -> Request {
+        self.request(Method::Options, url)
+    }
+
+    /// Alias for [Client::request](#method.request) with `method` set
+    /// to [Method::Patch](enum.Method.html).
+    pub fn patch<T: Into<URL>>(&self, url: T) -> Request {
+        self.request(Method::Patch, url)
+    }
+}
+
+impl Default for Client {
+    fn default() -> Client {
+        Client::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Client;
+    use crate::request::Method;
+    use core::time::Duration;
+
+    #[test]
+    fn client_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Client>();
+    }
+
+    #[test]
+    fn resolve_against_base_url() {
+        let client = Client::new().with_base_url("http://example.com/api/");
+        assert_eq!(client.get("/users").url, "http://example.com/api/users");
+        assert_eq!(client.get("users").url, "http://example.com/api/users");
+        assert_eq!(client.get("").url, "http://example.com/api");
+        assert_eq!(
+            client.get("https://example.org/x").url,
+            "https://example.org/x"
+        );
+        assert_eq!(Client::new().get("http://a.b/c").url, "http://a.b/c");
+    }
+
+    #[test]
+    fn requests_inherit_defaults() {
+        let client = Client::new()
+            .with_header("Accept", "text/plain")
+            .with_timeout(5)
+            .with_max_redirects(3);
+        let request = client.request(Method::Put, "http://example.com");
+        assert_eq!(request.method, Method::Put);
+        assert_eq!(request.timeout, Some(Duration::from_secs(5)));
+        assert_eq!(request.pool.as_ref(), Some(client.pool()));
+
+        let overridden = client.get("http://example.com").with_timeout(1);
+        assert_eq!(overridden.timeout, Some(Duration::from_secs(1)));
+        assert_eq!(
+            client.get("http://example.com").timeout,
+            Some(Duration::from_secs(5))
+        );
+    }
+}
@@ -1,78 +1,237 @@
+use crate::pool::PoolKey;
+#[cfg(feature = "proxy")]
+use crate::proxy::socks::{self, Destination};
+#[cfg(feature = "proxy")]
+use crate::proxy::{Proxy, ProxyKind};
 use crate::request::ParsedRequest;
-use crate::{Error, Method, ResponseLazy};
+#[cfg(feature = "proxy")]
+use crate::response::ConnectResponse;
+use crate::{Error, Method, RedirectAttempt, RedirectHop, ResponseLazy, RetryOutcome, TimeoutKind};
 use core::time::Duration;
 use std::env;
-use std::io::{self, Read, Write};
-use std::net::{TcpStream, ToSocketAddrs};
+use std::io::{self, BufWriter, Read, Write};
+#[cfg(feature = "proxy")]
+use std::net::IpAddr;
+use std::net::TcpStream;
+use std::thread;
 use std::time::Instant;
 
-type UnsecuredStream = TcpStream;
+type UnsecuredStream = Transport;
 
+mod resolver;
 #[cfg(feature = "rustls")]
 mod rustls_stream;
 #[cfg(feature = "rustls")]
 type SecuredStream = rustls_stream::SecuredStream;
+#[cfg(feature = "rustls")]
+pub(crate) use rustls_stream::TlsConfig;
+
+/// The connection that requests, or TLS sessions with servers, are
+/// sent over: a TCP connection to the server or a proxy, or a TLS
+/// session with an HTTPS proxy.
+pub(crate) enum Transport {
+    Tcp(TcpStream),
+    #[cfg(all(feature = "proxy", feature = "rustls"))]
+    Tls(Box<SecuredStream>),
+}
+
+impl Transport {
+    fn tcp(&self) -> &TcpStream {
+        match self {
+            Transport::Tcp(tcp) => tcp,
+            #[cfg(all(feature = "proxy", feature = "rustls"))]
+            Transport::Tls(tls) => tls.get_ref().tcp(),
+        }
+    }
+}
+
+impl Read for Transport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Transport::Tcp(tcp) => tcp.read(buf),
+            #[cfg(all(feature = "proxy", feature = "rustls"))]
+            Transport::Tls(tls) => tls.read(buf),
+        }
+    }
+}
+
+impl Write for Transport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Transport::Tcp(tcp) => tcp.write(buf),
+            #[cfg(all(feature = "proxy", feature = "rustls"))]
+            Transport::Tls(tls) => tls.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Transport::Tcp(tcp) => tcp.flush(),
+            #[cfg(all(feature = "proxy", feature = "rustls"))]
+            Transport::Tls(tls) => tls.flush(),
+        }
+    }
+}
 
 pub(crate) enum HttpStream {
-    Unsecured(UnsecuredStream, Option<Instant>),
+    Unsecured(UnsecuredStream, StreamTimeouts),
     #[cfg(feature = "rustls")]
-    Secured(Box<SecuredStream>, Option<Instant>),
+    Secured(Box<SecuredStream>, StreamTimeouts),
+}
+
+/// The limits on how long reads from and writes to a stream may block.
+#[derive(Clone, Copy)]
+pub(crate) struct StreamTimeouts {
+    /// When the request's total timeout is reached.
+    deadline: Option<Instant>,
+    /// How long a read may wait for data to arrive.
+    read: Option<Duration>,
+    /// How long a write may wait for the data to be sent.
+    write: Option<Duration>,
 }
 
 impl HttpStream {
-    fn create_unsecured(reader: UnsecuredStream, timeout_at: Option<Instant>) -> HttpStream {
-        HttpStream::Unsecured(reader, timeout_at)
+    fn create_unsecured(reader: UnsecuredStream, timeouts: StreamTimeouts) -> HttpStream {
+        HttpStream::Unsecured(reader, timeouts)
     }
 
     #[cfg(feature = "rustls")]
-    fn create_secured(reader: SecuredStream, timeout_at: Option<Instant>) -> HttpStream {
-        HttpStream::Secured(Box::new(reader), timeout_at)
+    fn create_secured(reader: SecuredStream, timeouts: StreamTimeouts) -> HttpStream {
+        HttpStream::Secured(Box::new(reader), timeouts)
+    }
+
+    fn tcp(&self) -> &TcpStream {
+        match self {
+            HttpStream::Unsecured(inner, _) => inner.tcp(),
+            #[cfg(feature = "rustls")]
+            HttpStream::Secured(inner, _) => inner.get_ref().tcp(),
+        }
+    }
+
+    fn timeouts(&self) -> StreamTimeouts {
+        match self {
+            HttpStream::Unsecured(_, timeouts) => *timeouts,
+            #[cfg(feature = "rustls")]
+            HttpStream::Secured(_, timeouts) => *timeouts,
+        }
+    }
+
+    fn set_timeouts(&mut self, new_timeouts: StreamTimeouts) {
+        match self {
+            HttpStream::Unsecured(_, timeouts) => *timeouts = new_timeouts,
+            #[cfg(feature = "rustls")]
+            HttpStream::Secured(_, timeouts) => *timeouts = new_timeouts,
+        }
+    }
+
+    /// Checks if an idle connection can be used for another request,
+    /// ie. the server has not closed it, and has not sent anything
+    /// unexpected while it was idle.
+    pub(crate) fn is_reusable(&self) -> bool {
+        let tcp = self.tcp();
+        if tcp.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let mut buf = [0; 1];
+        let peeked = tcp.peek(&mut buf);
+        if tcp.set_nonblocking(false).is_err() {
+            return false;
+        }
+        match peeked {
+            // The server has closed the connection.
+            Ok(0) => false,
+            // Plain HTTP servers shouldn't send anything between
+            // responses, but TLS servers (and HTTPS proxies) can send
+            // eg. session tickets, which rustls will handle.
+            #[cfg(feature = "rustls")]
+            Ok(_) if matches!(self, HttpStream::Secured(..)) => true,
+            #[cfg(all(feature = "proxy", feature = "rustls"))]
+            Ok(_) if matches!(self, HttpStream::Unsecured(Transport::Tls(_), _)) => true,
+            Ok(_) => false,
+            Err(err) => err.kind() == io::ErrorKind::WouldBlock,
+        }
     }
 }
 
-fn timeout_err() -> io::Error {
-    io::Error::new(
-        io::ErrorKind::TimedOut,
-        "the timeout of the request was reached",
-    )
+fn timeout_err(kind: TimeoutKind) -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, kind)
 }
 
-fn timeout_at_to_duration(timeout_at: Option<Instant>) -> Result<Option<Duration>, io::Error> {
-    if let Some(timeout_at) = timeout_at {
-        if let Some(duration) = timeout_at.checked_duration_since(Instant::now()) {
-            Ok(Some(duration))
-        } else {
-            Err(timeout_err())
+/// Returns how long an operation may block, given the request's total
+/// `deadline` and the operation's own `limit`, along with the kind of
+/// timeout that would be reached first.
+///
+/// Returns an error if there's no time left.
+fn time_limit(
+    deadline: Option<Instant>,
+    limit: Option<Duration>,
+    kind: TimeoutKind,
+) -> io::Result<Option<(Duration, TimeoutKind)>> {
+    let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+    let limit = match (remaining, limit) {
+        (Some(remaining), Some(limit)) if limit < remaining => Some((limit, kind)),
+        (Some(remaining), _) => Some((remaining, TimeoutKind::Total)),
+        (None, limit) => limit.map(|limit| (limit, kind)),
+    };
+    #[cfg(feature = "log")]
+    log::trace!("Timeout requested, it is currently: {:?}", limit);
+    match limit {
+        Some((limit, kind)) if limit.is_zero() => Err(timeout_err(kind)),
+        limit => Ok(limit),
+    }
+}
+
+/// Replaces the error of an operation that blocked for longer than
+/// `limit` with the error of the timeout that was reached.
+fn map_timeout_err(err: io::Error, limit: Option<(Duration, TimeoutKind)>) -> io::Error {
+    match limit {
+        // We're a blocking socket, so EWOULDBLOCK indicates a timeout
+        Some((_, kind))
+            if matches!(
+                err.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) =>
+        {
+            timeout_err(kind)
         }
-    } else {
-        Ok(None)
+        _ => err,
     }
 }
 
 impl Read for HttpStream {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let timeout = |tcp: &TcpStream, timeout_at: Option<Instant>| -> io::Result<()> {
-            let _ = tcp.set_read_timeout(timeout_at_to_duration(timeout_at)?);
-            Ok(())
+        let timeouts = self.timeouts();
+        let limit = time_limit(timeouts.deadline, timeouts.read, TimeoutKind::Read)?;
+        let _ = self.tcp().set_read_timeout(limit.map(|(limit, _)| limit));
+
+        let result = match self {
+            HttpStream::Unsecured(inner, _) => inner.read(buf),
+            #[cfg(feature = "rustls")]
+            HttpStream::Secured(inner, _) => inner.read(buf),
         };
+        result.map_err(|err| map_timeout_err(err, limit))
+    }
+}
+
+impl Write for HttpStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let timeouts = self.timeouts();
+        let limit = time_limit(timeouts.deadline, timeouts.write, TimeoutKind::Write)?;
+        let _ = self.tcp().set_write_timeout(limit.map(|(limit, _)| limit));
 
         let result = match self {
-            HttpStream::Unsecured(inner, timeout_at) => {
-                timeout(inner, *timeout_at)?;
-                inner.read(buf)
-            }
+            HttpStream::Unsecured(inner, _) => inner.write(buf),
             #[cfg(feature = "rustls")]
-            HttpStream::Secured(inner, timeout_at) => {
-                timeout(inner.get_ref(), *timeout_at)?;
-                inner.read(buf)
-            }
+            HttpStream::Secured(inner, _) => inner.write(buf),
         };
-        match result {
-            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
-                // We're a blocking socket, so EWOULDBLOCK indicates a timeout
-                Err(timeout_err())
-            }
-            r => r,
+        result.map_err(|err| map_timeout_err(err, limit))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            HttpStream::Unsecured(inner, _) => inner.flush(),
+            #[cfg(feature = "rustls")]
+            HttpStream::Secured(inner, _) => inner.flush(),
         }
     }
 }
@@ -89,37 +248,17 @@ pub struct AsyncConnection {
 impl AsyncConnection {
     /// Creates a new `AsyncConnection`.
     pub(crate) fn new(request: ParsedRequest) -> AsyncConnection {
-        let timeout = request
-            .config
-            .timeout
-            .or_else(|| match env::var("MINREQ_TIMEOUT") {
-                Ok(t) => t.parse::<u64>().ok(),
-                Err(_) => None,
-            });
-        let timeout_at = timeout.map(|t| Instant::now() + Duration::from_secs(t));
+        let timeout_at = total_timeout(&request).map(|t| Instant::now() + t);
         AsyncConnection {
             request,
             timeout_at,
         }
     }
 
-    /// Sends the [`Request`](struct.Request.html) asynchronously using HTTPS.
-    #[cfg(feature = "async-https")]
-    pub(crate) async fn send_https(self) -> Result<ResponseLazy, Error> {
-        // Use spawn_blocking to run the sync HTTPS code in a thread pool
-        let sync_conn = Connection {
-            request: self.request,
-            timeout_at: self.timeout_at,
-        };
-
-        tokio::task::spawn_blocking(move || sync_conn.send_https())
-            .await
-            .map_err(|e| Error::IoError(io::Error::new(io::ErrorKind::Other, e)))?
-    }
-
-    /// Sends the [`Request`](struct.Request.html) asynchronously using HTTP.
+    /// Sends the [`Request`](struct.Request.html) asynchronously,
+    /// following its redirects. See [`Connection::send`].
     pub(crate) async fn send(self) -> Result<ResponseLazy, Error> {
-        // Use spawn_blocking to run the sync HTTP code in a thread pool
+        // Use spawn_blocking to run the sync code in a thread pool
         let sync_conn = Connection {
             request: self.request,
             timeout_at: self.timeout_at,
@@ -142,96 +281,163 @@ impl Connection {
     /// Creates a new `Connection`. See [Request] and [ParsedRequest]
     /// for specifics about *what* is being sent.
     pub(crate) fn new(request: ParsedRequest) -> Connection {
-        let timeout = request
-            .config
-            .timeout
-            .or_else(|| match env::var("MINREQ_TIMEOUT") {
-                Ok(t) => t.parse::<u64>().ok(),
-                Err(_) => None,
-            });
-        let timeout_at = timeout.map(|t| Instant::now() + Duration::from_secs(t));
+        let timeout_at = total_timeout(&request).map(|t| Instant::now() + t);
         Connection {
             request,
             timeout_at,
         }
     }
 
-    /// Returns the timeout duration for operations that should end at
-    /// timeout and are starting "now".
-    ///
-    /// The Result will be Err if the timeout has already passed.
-    fn timeout(&self) -> Result<Option<Duration>, io::Error> {
-        let timeout = timeout_at_to_duration(self.timeout_at);
-        #[cfg(feature = "log")]
-        log::trace!("Timeout requested, it is currently: {:?}", timeout);
-        timeout
+    fn stream_timeouts(&self) -> StreamTimeouts {
+        StreamTimeouts {
+            deadline: self.timeout_at,
+            read: self.request.config.read_timeout,
+            write: self.request.config.write_timeout,
+        }
     }
 
-    /// Sends the [`Request`](struct.Request.html), consumes this
-    /// connection, and returns a [`Response`](struct.Response.html).
-    #[cfg(feature = "rustls")]
-    pub(crate) fn send_https(mut self) -> Result<ResponseLazy, Error> {
-        enforce_timeout(self.timeout_at, move || {
-            self.request.url.host = ensure_ascii_host(self.request.url.host)?;
-
-            let secured_stream = rustls_stream::create_secured_stream(&self)?;
+    /// Sends the [`Request`](struct.Request.html) and follows its
+    /// redirects, retrying it as its retry policy allows, consumes this
+    /// connection, and returns the final
+    /// [`Response`](struct.Response.html).
+    pub(crate) fn send(self) -> Result<ResponseLazy, Error> {
+        let policy = match self.request.config.retry_policy {
+            Some(ref policy) if policy.retries_method(&self.request.config.method) => {
+                policy.clone()
+            }
+            _ => return follow_redirects(self),
+        };
 
+        let mut attempts = 1;
+        loop {
+            let result = follow_redirects(Connection {
+                request: self.request.clone(),
+                timeout_at: self.timeout_at,
+            });
+            let outcome = match result {
+                Ok(ref response) => RetryOutcome::Response(response),
+                Err(ref err) => RetryOutcome::Error(err),
+            };
+            let delay = match policy.retry_delay(attempts, &outcome) {
+                // A streamed body can't be sent again, and there's no
+                // point in waiting past the total timeout.
+                Some(delay)
+                    if self.request.is_replayable()
+                        && !matches!(self.timeout_at, Some(at) if Instant::now() + delay >= at) =>
+                {
+                    delay
+                }
+                _ => {
+                    return result.map(|mut response| {
+                        response.attempts = attempts;
+                        response
+                    })
+                }
+            };
             #[cfg(feature = "log")]
-            log::trace!("Reading HTTPS response from {}.", self.request.url.host);
-            let response = ResponseLazy::from_stream(
-                secured_stream,
-                self.request.config.max_headers_size,
-                self.request.config.max_status_line_len,
-            )?;
-
-            handle_redirects(self, response)
-        })
+            log::debug!("Attempt {} failed, retrying in {:?}.", attempts, delay);
+            thread::sleep(delay);
+            attempts += 1;
+        }
     }
 
-    /// Sends the [`Request`](struct.Request.html), consumes this
-    /// connection, and returns a [`Response`](struct.Response.html).
-    pub(crate) fn send(mut self) -> Result<ResponseLazy, Error> {
-        enforce_timeout(self.timeout_at, move || {
-            self.request.url.host = ensure_ascii_host(self.request.url.host)?;
-            let bytes = self.request.as_bytes();
+    /// Writes the request to a pooled connection if there is one, or
+    /// a new one otherwise, and reads the response's metadata.
+    fn exchange(&self) -> Result<ResponseLazy, Error> {
+        let pool =
+            (self.request.config.pool.as_ref()).filter(|_| !self.request.closes_connection());
+        let pool_key = pool.map(|_| PoolKey::new(&self.request));
+
+        if let (Some(pool), Some(key)) = (pool, &pool_key) {
+            if let Some(mut stream) = pool.acquire(key) {
+                stream.set_timeouts(self.stream_timeouts());
+                match self.write_request(&mut stream) {
+                    Ok(()) => return self.read_response(stream, pool_key),
+                    // A streamed body can't be sent again if it was
+                    // already (partially) read.
+                    Err(err) if !self.request.is_replayable() => return Err(err),
+                    // The server may have closed the connection after
+                    // our liveness check, try again with a new one.
+                    Err(_err) => {
+                        #[cfg(feature = "log")]
+                        log::debug!("Pooled connection could not be written to: {}", _err);
+                    }
+                }
+            }
+        }
 
-            #[cfg(feature = "log")]
-            log::trace!("Establishing TCP connection to {}.", self.request.url.host);
-            let mut tcp = self.connect()?;
+        let mut stream = self.open_stream()?;
+        self.write_request(&mut stream)?;
+        self.read_response(stream, pool_key)
+    }
 
-            // Send request
-            #[cfg(feature = "log")]
-            log::trace!("Writing HTTP request.");
-            let _ = tcp.set_write_timeout(self.timeout()?);
-            tcp.write_all(&bytes)?;
+    /// Opens a new connection to the server, secured with TLS if the
+    /// request is for an https url (and isn't forwarded by a proxy).
+    fn open_stream(&self) -> Result<HttpStream, Error> {
+        if self.request.url.https && !self.request.is_forwarded() {
+            #[cfg(feature = "rustls")]
+            return rustls_stream::create_secured_stream(self);
+            #[cfg(not(feature = "rustls"))]
+            return Err(Error::HttpsFeatureNotEnabled);
+        }
 
-            // Receive response
-            #[cfg(feature = "log")]
-            log::trace!("Reading HTTP response.");
-            let stream = HttpStream::create_unsecured(tcp, self.timeout_at);
-            let response = ResponseLazy::from_stream(
-                stream,
-                self.request.config.max_headers_size,
-                self.request.config.max_status_line_len,
-            )?;
-            handle_redirects(self, response)
-        })
+        #[cfg(feature = "log")]
+        log::trace!("Establishing TCP connection to {}.", self.request.url.host);
+        let transport = self.connect()?;
+        Ok(HttpStream::create_unsecured(
+            transport,
+            self.stream_timeouts(),
+        ))
+    }
+
+    fn write_request(&self, stream: &mut HttpStream) -> Result<(), Error> {
+        #[cfg(feature = "log")]
+        log::trace!("Writing HTTP request to {}.", self.request.url.host);
+        let mut writer = BufWriter::new(stream);
+        self.request.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
     }
 
-    fn connect(&self) -> Result<TcpStream, Error> {
+    fn read_response(
+        &self,
+        stream: HttpStream,
+        pool_key: Option<PoolKey>,
+    ) -> Result<ResponseLazy, Error> {
+        #[cfg(feature = "log")]
+        log::trace!("Reading HTTP response from {}.", self.request.url.host);
+        let mut response = ResponseLazy::from_stream(
+            stream,
+            self.request.config.method == Method::Head,
+            self.request.config.decompress,
+            self.request.config.max_headers_size,
+            self.request.config.max_status_line_len,
+        )?;
+        if let (Some(pool), Some(key)) = (&self.request.config.pool, pool_key) {
+            response.return_connection_to(pool.clone(), key);
+        }
+        Ok(response)
+    }
+
+    /// Connects to the server, or opens a tunnel to it through the
+    /// request's proxy.
+    fn connect(&self) -> Result<Transport, Error> {
         let tcp_connect = |host: &str, port: u32| -> Result<TcpStream, Error> {
-            let addrs = (host, port as u16)
-                .to_socket_addrs()
-                .map_err(Error::IoError)?;
+            let addrs = resolver::resolve(host, port as u16, self.timeout_at)?;
             let addrs_count = addrs.len();
 
             // Try all resolved addresses. Return the first one to which we could connect. If all
             // failed return the last error encountered.
-            for (i, addr) in addrs.enumerate() {
-                let stream = if let Some(timeout) = self.timeout()? {
-                    TcpStream::connect_timeout(&addr, timeout)
-                } else {
-                    TcpStream::connect(addr)
+            for (i, addr) in addrs.into_iter().enumerate() {
+                let limit = time_limit(
+                    self.timeout_at,
+                    self.request.config.connect_timeout,
+                    TimeoutKind::Connect,
+                )?;
+                let stream = match limit {
+                    Some((timeout, _)) => TcpStream::connect_timeout(&addr, timeout)
+                        .map_err(|err| map_timeout_err(err, limit)),
+                    None => TcpStream::connect(addr),
                 };
                 if stream.is_ok() || i == addrs_count - 1 {
                     return stream.map_err(Error::from);
@@ -243,96 +449,248 @@ impl Connection {
 
         #[cfg(feature = "proxy")]
         match self.request.config.proxy {
+            Some(ref proxy) if proxy.is_socks() => {
+                let (host, port) = (&self.request.url.host, self.request.url.port.port());
+                let destination = match host.parse::<IpAddr>() {
+                    Ok(ip) => Destination::Ip(ip),
+                    Err(_) if proxy.kind == ProxyKind::Socks5 => {
+                        let addrs = resolver::resolve(host, port as u16, self.timeout_at)?;
+                        let addr = addrs.first().ok_or(Error::AddressNotFound)?;
+                        Destination::Ip(addr.ip())
+                    }
+                    Err(_) => Destination::Host(host),
+                };
+                let tcp = tcp_connect(&proxy.server, proxy.port)?;
+                let mut stream = HttpStream::create_unsecured(
+                    Transport::Tcp(tcp.try_clone()?),
+                    self.stream_timeouts(),
+                );
+                socks::handshake(&mut stream, proxy, destination, port as u16)?;
+                Ok(Transport::Tcp(tcp))
+            }
+            Some(ref proxy) if self.request.is_forwarded() => {
+                let tcp = tcp_connect(&proxy.server, proxy.port)?;
+                self.secure_proxy_connection(proxy, tcp)
+            }
             Some(ref proxy) => {
-                // do proxy things
-                let mut tcp = tcp_connect(&proxy.server, proxy.port)?;
-
-                write!(tcp, "{}", proxy.connect(&self.request)).unwrap();
-                tcp.flush()?;
-
-                let mut proxy_response = Vec::new();
-
+                // The CONNECT request is sent a second time, on a new
+                // connection, if the proxy answers with a Digest challenge.
+                let mut authorization = None;
                 loop {
-                    let mut buf = vec![0; 256];
-                    let total = tcp.read(&mut buf)?;
-                    proxy_response.append(&mut buf);
-                    if total < 256 {
-                        break;
+                    // do proxy things
+                    let tcp = tcp_connect(&proxy.server, proxy.port)?;
+                    // The credentials in the CONNECT request are only sent
+                    // once the TLS session with an HTTPS proxy is set up.
+                    let transport = self.secure_proxy_connection(proxy, tcp)?;
+                    // Talk to the proxy through a handle that applies the
+                    // request's timeouts to the socket.
+                    let mut stream =
+                        HttpStream::create_unsecured(transport, self.stream_timeouts());
+
+                    write!(
+                        stream,
+                        "{}",
+                        proxy.connect(&self.request, authorization.as_deref())
+                    )?;
+                    stream.flush()?;
+
+                    let proxy_response = ConnectResponse::read(
+                        &mut stream,
+                        self.request.config.max_headers_size,
+                        self.request.config.max_status_line_len,
+                    )?;
+
+                    if authorization.is_none() {
+                        let url = &self.request.url;
+                        authorization = proxy.digest_authorization(
+                            proxy_response.status_code,
+                            &proxy_response.headers,
+                            "CONNECT",
+                            &format!("{}:{}", url.host, url.port.port()),
+                        );
+                        if authorization.is_some() {
+                            continue;
+                        }
                     }
-                }
-
-                crate::Proxy::verify_response(&proxy_response)?;
+                    crate::Proxy::verify_response(&proxy_response)?;
 
-                Ok(tcp)
+                    return match stream {
+                        HttpStream::Unsecured(transport, _) => Ok(transport),
+                        #[cfg(feature = "rustls")]
+                        HttpStream::Secured(..) => Err(Error::Other("proxy stream was secured")),
+                    };
+                }
             }
-            None => tcp_connect(&self.request.url.host, self.request.url.port.port()),
+            None => tcp_connect(&self.request.url.host, self.request.url.port.port())
+                .map(Transport::Tcp),
         }
 
         #[cfg(not(feature = "proxy"))]
-        tcp_connect(&self.request.url.host, self.request.url.port.port())
+        tcp_connect(&self.request.url.host, self.request.url.port.port()).map(Transport::Tcp)
+    }
+
+    /// Starts a TLS session over the connection to the proxy, if it's
+    /// an HTTPS proxy.
+    #[cfg(feature = "proxy")]
+    fn secure_proxy_connection(&self, proxy: &Proxy, tcp: TcpStream) -> Result<Transport, Error> {
+        if !proxy.https {
+            return Ok(Transport::Tcp(tcp));
+        }
+        #[cfg(feature = "rustls")]
+        {
+            let session = rustls_stream::new_session(self, &proxy.server)?;
+            #[cfg(feature = "log")]
+            log::trace!("Establishing TLS session to proxy {}.", proxy.server);
+            let tls = rustls_stream::handshake(self, session, Transport::Tcp(tcp))?;
+            Ok(Transport::Tls(Box::new(tls)))
+        }
+        #[cfg(not(feature = "rustls"))]
+        Err(Error::HttpsFeatureNotEnabled)
     }
 }
 
-fn handle_redirects(
-    connection: Connection,
-    mut response: ResponseLazy,
-) -> Result<ResponseLazy, Error> {
-    let status_code = response.status_code;
-    let url = response.headers.get("location");
-    match get_redirect(connection, status_code, url) {
-        NextHop::Redirect(connection) => {
-            let connection = connection?;
-            if connection.request.url.https {
-                #[cfg(not(feature = "rustls"))]
-                return Err(Error::HttpsFeatureNotEnabled);
-                #[cfg(feature = "rustls")]
-                return connection.send_https();
-            } else {
-                connection.send()
+/// Sends the request, and the requests for each redirect that the
+/// response leads to, until a response that isn't followed is received.
+///
+/// This is a loop rather than recursion, so that long redirect chains
+/// don't need any more stack than short ones.
+fn follow_redirects(mut connection: Connection) -> Result<ResponseLazy, Error> {
+    loop {
+        connection.request.url.host = ensure_ascii_host(connection.request.url.host)?;
+        #[cfg(feature = "digest-auth")]
+        let mut response = exchange_authenticated(&mut connection)?;
+        #[cfg(not(feature = "digest-auth"))]
+        let mut response = connection.exchange()?;
+        if let Some(ref jar) = connection.request.config.cookie_jar {
+            jar.store_response(&connection.request.url, &response.headers);
+        }
+        connection = match get_redirect(connection, &response)? {
+            NextHop::Redirect(connection) => connection,
+            NextHop::Destination(connection) => {
+                let dst_url = connection.request.url;
+                dst_url.write_base_url_to(&mut response.url).unwrap();
+                dst_url.write_resource_to(&mut response.url).unwrap();
+                response.redirect_history = connection.request.redirect_history;
+                return Ok(response);
             }
+        };
+    }
+}
+
+/// Sends the request, answering the Digest challenges of the server,
+/// and of the proxy the request is forwarded by, if there are
+/// credentials for them. Any challenge answered earlier with the same
+/// server credentials is answered up front, with the next nonce count.
+#[cfg(feature = "digest-auth")]
+fn exchange_authenticated(connection: &mut Connection) -> Result<ResponseLazy, Error> {
+    let method = connection.request.config.method.to_string();
+    // The request target, which is an absolute URL when the request is
+    // forwarded by a proxy.
+    let uri = connection.request.request_target();
+    let digest_auth = connection.request.config.digest_auth.clone();
+
+    let preemptive = (digest_auth.as_ref()).and_then(|auth| auth.preemptive(&method, &uri));
+    if let Some(authorization) = preemptive {
+        let headers = &mut connection.request.config.headers;
+        headers.insert("Authorization", authorization);
+    }
+    let mut response = connection.exchange()?;
+    if !connection.request.is_replayable() {
+        return Ok(response);
+    }
+
+    #[cfg(feature = "proxy")]
+    if connection.request.is_forwarded() {
+        let authorization = (connection.request.config.proxy.as_ref()).and_then(|proxy| {
+            proxy.digest_authorization(response.status_code, &response.headers, &method, &uri)
+        });
+        if let Some(authorization) = authorization {
+            let headers = &mut connection.request.config.headers;
+            headers.insert("Proxy-Authorization", authorization);
+            #[cfg(feature = "log")]
+            log::debug!("Answering the Digest challenge from the proxy.");
+            response = connection.exchange()?;
         }
-        NextHop::Destination(connection) => {
-            let dst_url = connection.request.url;
-            dst_url.write_base_url_to(&mut response.url).unwrap();
-            dst_url.write_resource_to(&mut response.url).unwrap();
-            Ok(response)
+    }
+
+    if let (401, Some(digest_auth)) = (response.status_code, &digest_auth) {
+        let challenges = response.headers.get_all("www-authenticate");
+        if let Some(authorization) = digest_auth.respond(challenges, &method, &uri) {
+            let headers = &mut connection.request.config.headers;
+            headers.insert("Authorization", authorization);
+            #[cfg(feature = "log")]
+            log::debug!(
+                "Answering the Digest challenge from {}.",
+                connection.request.url.host
+            );
+            response = connection.exchange()?;
         }
     }
+    Ok(response)
 }
 
+/// What to do after a response has been received.
 enum NextHop {
-    Redirect(Result<Connection, Error>),
+    /// Send the request again, to the location it was redirected to.
+    Redirect(Connection),
+    /// Return the response to the caller.
     Destination(Connection),
 }
 
-fn get_redirect(mut connection: Connection, status_code: i32, url: Option<&String>) -> NextHop {
-    match status_code {
-        301 | 302 | 303 | 307 => {
-            let url = match url {
-                Some(url) => url,
-                None => return NextHop::Redirect(Err(Error::RedirectLocationMissing)),
-            };
-            #[cfg(feature = "log")]
-            log::debug!("Redirecting ({}) to: {}", status_code, url);
-
-            match connection.request.redirect_to(url.as_str()) {
-                Ok(()) => {
-                    if status_code == 303 {
-                        match connection.request.config.method {
-                            Method::Post | Method::Put | Method::Delete => {
-                                connection.request.config.method = Method::Get;
-                            }
-                            _ => {}
-                        }
-                    }
+fn get_redirect(mut connection: Connection, response: &ResponseLazy) -> Result<NextHop, Error> {
+    let status_code = response.status_code;
+    let policy = connection.request.config.redirect_policy.clone();
+    if !matches!(status_code, 301 | 302 | 303 | 307 | 308) || !policy.follows_redirects() {
+        return Ok(NextHop::Destination(connection));
+    }
 
-                    NextHop::Redirect(Ok(connection))
-                }
-                Err(err) => NextHop::Redirect(Err(err)),
-            }
-        }
-        _ => NextHop::Destination(connection),
+    let location = match response.headers.get("location") {
+        Some(location) => location,
+        None => return Err(Error::RedirectLocationMissing),
+    };
+    let (method, keep_body) =
+        policy.redirected_method(status_code, &connection.request.config.method);
+    // A streamed body has been sent already and can't be sent to the
+    // new location, so the redirect is returned as-is.
+    if keep_body && !connection.request.is_replayable() {
+        return Ok(NextHop::Destination(connection));
+    }
+    let url = connection.request.redirect_url(location)?;
+    if connection.request.url.https && !url.https && !policy.allows_https_downgrade() {
+        return Err(Error::InsecureRedirect);
+    }
+
+    let current_url = connection.request.url.to_string();
+    let next_url = url.to_string();
+    let attempt = RedirectAttempt {
+        status_code,
+        url: &current_url,
+        location,
+        next_url: &next_url,
+        next_method: &method,
+        headers: &response.headers,
+        previous_redirects: connection.request.redirects.len(),
+    };
+    if !policy.allows(&attempt) {
+        return Ok(NextHop::Destination(connection));
     }
+    #[cfg(feature = "log")]
+    log::debug!("Redirecting ({}) to: {}", status_code, next_url);
+
+    connection.request.redirect_history.push(RedirectHop {
+        url: current_url,
+        status_code,
+        location: location.to_string(),
+        headers: response.headers.clone(),
+    });
+    let cross_origin = !connection.request.url.same_origin(&url);
+    connection.request.redirect_to(url)?;
+    if cross_origin {
+        connection.request.strip_credentials();
+    }
+    connection.request.apply_url_credentials();
+    connection.request.set_method(method, keep_body);
+    Ok(NextHop::Redirect(connection))
 }
 
 fn ensure_ascii_host(host: String) -> Result<String, Error> {
@@ -364,41 +722,14 @@ fn ensure_ascii_host(host: String) -> Result<String, Error> {
     }
 }
 
-/// Enforce the timeout by running the function in a new thread and
-/// parking the current one with a timeout.
-///
-/// While minireq does use timeouts (somewhat) properly, some
-/// interfaces such as [ToSocketAddrs] don't allow for specifying the
-/// timeout. Hence this.
-fn enforce_timeout<F, R>(timeout_at: Option<Instant>, f: F) -> Result<R, Error>
-where
-    F: 'static + Send + FnOnce() -> Result<R, Error>,
-    R: 'static + Send,
-{
-    use std::sync::mpsc::{channel, RecvTimeoutError};
-
-    match timeout_at {
-        Some(deadline) => {
-            let (sender, receiver) = channel();
-            let thread = std::thread::spawn(move || {
-                let result = f();
-                let _ = sender.send(());
-                result
-            });
-            if let Some(timeout_duration) = deadline.checked_duration_since(Instant::now()) {
-                match receiver.recv_timeout(timeout_duration) {
-                    Ok(()) => thread.join().unwrap(),
-                    Err(err) => match err {
-                        RecvTimeoutError::Timeout => Err(Error::IoError(timeout_err())),
-                        RecvTimeoutError::Disconnected => {
-                            Err(Error::Other("request connection paniced"))
-                        }
-                    },
-                }
-            } else {
-                Err(Error::IoError(timeout_err()))
-            }
-        }
-        None => f(),
-    }
+/// Returns the request's total timeout, falling back to the
+/// `MINREQ_TIMEOUT` environment variable (in seconds).
+fn total_timeout(request: &ParsedRequest) -> Option<Duration> {
+    request
+        .config
+        .timeout
+        .or_else(|| match env::var("MINREQ_TIMEOUT") {
+            Ok(t) => t.parse::<u64>().ok().map(Duration::from_secs),
+            Err(_) => None,
+        })
 }
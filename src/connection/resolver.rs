@@ -0,0 +1,189 @@
+//! Host name resolution that can be given up on when a request's
+//! timeout is reached.
+//!
+//! [`ToSocketAddrs`] blocks until the system resolver answers, with no
+//! way to set a timeout. So lookups for requests with a timeout are
+//! done on a small shared pool of threads, and the request stops
+//! waiting once its time is up. The pool never has more than
+//! [`MAX_THREADS`] threads, and they exit after being idle for a while.
+
+use crate::{Error, TimeoutKind};
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MAX_THREADS: usize = 4;
+const IDLE_THREAD_LIFETIME: Duration = Duration::from_secs(30);
+
+/// Resolves `host` to the addresses to connect to, failing with
+/// [`TimeoutKind::Total`] if it takes until `deadline`.
+pub(crate) fn resolve(
+    host: &str,
+    port: u16,
+    deadline: Option<Instant>,
+) -> Result<Vec<SocketAddr>, Error> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(vec![SocketAddr::new(ip, port)]);
+    }
+    let deadline = match deadline {
+        Some(deadline) => deadline,
+        // Without a deadline, blocking this thread is fine.
+        None => return Ok(lookup(host, port)?),
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    let job = Job {
+        host: host.to_string(),
+        port,
+        deadline,
+        result: sender,
+    };
+    if let Err(job) = pool().submit(job) {
+        // No resolver thread could be started, so resolve here, and
+        // check the deadline afterwards.
+        let addrs = lookup(&job.host, port)?;
+        return match deadline.checked_duration_since(Instant::now()) {
+            Some(_) => Ok(addrs),
+            None => Err(Error::Timeout(TimeoutKind::Total)),
+        };
+    }
+    match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        Ok(addrs) => Ok(addrs?),
+        Err(RecvTimeoutError::Timeout) => Err(Error::Timeout(TimeoutKind::Total)),
+        // The job is dropped without an answer if its deadline passed
+        // before a thread got to it.
+        Err(RecvTimeoutError::Disconnected) if Instant::now() >= deadline => {
+            Err(Error::Timeout(TimeoutKind::Total))
+        }
+        Err(RecvTimeoutError::Disconnected) => Err(Error::Other("resolver thread panicked")),
+    }
+}
+
+fn lookup(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    Ok((host, port).to_socket_addrs()?.collect())
+}
+
+fn pool() -> &'static ResolverPool {
+    static POOL: ResolverPool = ResolverPool {
+        state: Mutex::new(PoolState {
+            jobs: Vec::new(),
+            threads: 0,
+            idle_threads: 0,
+        }),
+        job_added: Condvar::new(),
+    };
+    &POOL
+}
+
+/// A lookup waiting for a resolver thread.
+struct Job {
+    host: String,
+    port: u16,
+    /// When the request stops waiting for the result, after which
+    /// there's no point in doing the lookup.
+    deadline: Instant,
+    result: Sender<io::Result<Vec<SocketAddr>>>,
+}
+
+struct ResolverPool {
+    state: Mutex<PoolState>,
+    job_added: Condvar,
+}
+
+struct PoolState {
+    /// The queued jobs, oldest first. There are only ever a few, so
+    /// this is a `Vec` rather than a `VecDeque`, which can't be created
+    /// in a `static` before Rust 1.68.
+    jobs: Vec<Job>,
+    threads: usize,
+    idle_threads: usize,
+}
+
+impl ResolverPool {
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Queues the job, and starts a new thread for it if all the
+    /// current ones are busy and there's room for one more. Returns the
+    /// job back if there are no threads to run it.
+    fn submit(&'static self, job: Job) -> Result<(), Job> {
+        let mut state = self.lock();
+        if state.idle_threads <= state.jobs.len() && state.threads < MAX_THREADS {
+            let spawned = thread::Builder::new()
+                .name("minireq-resolver".to_string())
+                .spawn(move || self.run_worker());
+            if spawned.is_ok() {
+                state.threads += 1;
+            } else if state.threads == 0 {
+                return Err(job);
+            }
+        }
+        state.jobs.push(job);
+        self.job_added.notify_one();
+        Ok(())
+    }
+
+    fn run_worker(&self) {
+        let mut state = self.lock();
+        loop {
+            if state.jobs.is_empty() {
+                state.idle_threads += 1;
+                let (new_state, wait) = self
+                    .job_added
+                    .wait_timeout(state, IDLE_THREAD_LIFETIME)
+                    .unwrap_or_else(|err| err.into_inner());
+                state = new_state;
+                state.idle_threads -= 1;
+                if wait.timed_out() && state.jobs.is_empty() {
+                    state.threads -= 1;
+                    return;
+                }
+                continue;
+            }
+            let job = state.jobs.remove(0);
+            drop(state);
+            if Instant::now() < job.deadline {
+                let _ = job.result.send(lookup(&job.host, job.port));
+            }
+            state = self.lock();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pool, resolve, MAX_THREADS};
+    use crate::{Error, TimeoutKind};
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn ip_literals() {
+        let addrs = resolve("127.0.0.1", 80, Some(Instant::now())).unwrap();
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        assert_eq!(addrs, vec![SocketAddr::new(localhost, 80)]);
+    }
+
+    #[test]
+    fn expired_deadline() {
+        let result = resolve("localhost", 80, Some(Instant::now()));
+        assert!(matches!(result, Err(Error::Timeout(TimeoutKind::Total))));
+    }
+
+    #[test]
+    fn bounded_threads() {
+        let deadline = Some(Instant::now() + Duration::from_secs(10));
+        let lookups = (0..20)
+            .map(|_| std::thread::spawn(move || resolve("localhost", 80, deadline)))
+            .collect::<Vec<_>>();
+        for lookup in lookups {
+            let addrs = lookup.join().unwrap().unwrap();
+            assert!(addrs.iter().all(|addr| addr.ip().is_loopback()));
+        }
+        assert!(pool().lock().threads <= MAX_THREADS);
+    }
+}
@@ -3,17 +3,19 @@
 
 use alloc::sync::Arc;
 use core::convert::TryFrom;
+use core::fmt;
+use core::hash::{Hash, Hasher};
 use rustls::{self, ClientConfig, ClientConnection, RootCertStore, ServerName, StreamOwned};
-use std::io::{self, Write};
-use std::net::TcpStream;
+use std::io;
+use std::time::Instant;
 #[cfg(feature = "rustls-webpki")]
 use webpki_roots::TLS_SERVER_ROOTS;
 
-use crate::Error;
+use crate::{Error, TimeoutKind};
 
-use super::{Connection, HttpStream};
+use super::{map_timeout_err, time_limit, Connection, HttpStream, Transport};
 
-pub type SecuredStream = StreamOwned<ClientConnection, TcpStream>;
+pub type SecuredStream = StreamOwned<ClientConnection, Transport>;
 
 static CONFIG: std::sync::LazyLock<Arc<ClientConfig>> = std::sync::LazyLock::new(|| {
     let mut root_certificates = RootCertStore::empty();
@@ -45,30 +47,93 @@ static CONFIG: std::sync::LazyLock<Arc<ClientConfig>> = std::sync::LazyLock::new
     Arc::new(config)
 });
 
+/// A user-provided rustls configuration, compared by identity so that
+/// requests using it can still be compared and pooled.
+#[derive(Clone)]
+pub(crate) struct TlsConfig(pub(crate) Arc<ClientConfig>);
+
+impl PartialEq for TlsConfig {
+    fn eq(&self, other: &TlsConfig) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for TlsConfig {}
+
+impl Hash for TlsConfig {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).hash(state);
+    }
+}
+
+impl fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("TlsConfig")
+            .field(&Arc::as_ptr(&self.0))
+            .finish()
+    }
+}
+
 pub fn create_secured_stream(conn: &Connection) -> Result<HttpStream, Error> {
-    // Rustls setup
-    #[cfg(feature = "log")]
-    log::trace!("Setting up TLS parameters for {}.", conn.request.url.host);
-    let dns_name = match ServerName::try_from(&*conn.request.url.host) {
-        Ok(result) => result,
-        Err(err) => return Err(Error::IoError(io::Error::new(io::ErrorKind::Other, err))),
-    };
-    let sess =
-        ClientConnection::new(CONFIG.clone(), dns_name).map_err(Error::RustlsCreateConnection)?;
+    let sess = new_session(conn, &conn.request.url.host)?;
 
     // Connect
     #[cfg(feature = "log")]
     log::trace!("Establishing TCP connection to {}.", conn.request.url.host);
-    let tcp = conn.connect()?;
+    let transport = conn.connect()?;
 
-    // Send request
     #[cfg(feature = "log")]
     log::trace!("Establishing TLS session to {}.", conn.request.url.host);
-    let mut tls = StreamOwned::new(sess, tcp); // I don't think this actually does any communication.
+    let tls = handshake(conn, sess, transport)?;
+    Ok(HttpStream::create_secured(tls, conn.stream_timeouts()))
+}
+
+/// Creates a TLS session with `host`, using the request's rustls
+/// configuration.
+pub(crate) fn new_session(conn: &Connection, host: &str) -> Result<ClientConnection, Error> {
+    // Rustls setup
     #[cfg(feature = "log")]
-    log::trace!("Writing HTTPS request to {}.", conn.request.url.host);
-    let _ = tls.get_ref().set_write_timeout(conn.timeout()?);
-    tls.write_all(&conn.request.as_bytes())?;
+    log::trace!("Setting up TLS parameters for {}.", host);
+    let dns_name = match ServerName::try_from(host) {
+        Ok(result) => result,
+        Err(err) => return Err(Error::IoError(io::Error::new(io::ErrorKind::Other, err))),
+    };
+    let config = match conn.request.config.tls_config {
+        Some(TlsConfig(ref config)) => config.clone(),
+        None => CONFIG.clone(),
+    };
+    ClientConnection::new(config, dns_name).map_err(Error::RustlsCreateConnection)
+}
 
-    Ok(HttpStream::create_secured(tls, conn.timeout_at))
+/// Starts the TLS session `sess` over `transport`, which may itself be
+/// a TLS session with an HTTPS proxy.
+pub(crate) fn handshake(
+    conn: &Connection,
+    sess: ClientConnection,
+    transport: Transport,
+) -> Result<SecuredStream, Error> {
+    let mut tls = StreamOwned::new(sess, transport);
+    // The handshake would otherwise happen during the first write,
+    // it's done here so that it has its own timeout.
+    let handshake_deadline = conn
+        .request
+        .config
+        .tls_handshake_timeout
+        .map(|timeout| Instant::now() + timeout);
+    while tls.conn.is_handshaking() {
+        let remaining = handshake_deadline.map(|at| at.saturating_duration_since(Instant::now()));
+        let limit = time_limit(conn.timeout_at, remaining, TimeoutKind::TlsHandshake)?;
+        let _ = tls
+            .sock
+            .tcp()
+            .set_read_timeout(limit.map(|(limit, _)| limit));
+        let _ = tls
+            .sock
+            .tcp()
+            .set_write_timeout(limit.map(|(limit, _)| limit));
+        tls.conn
+            .complete_io(&mut tls.sock)
+            .map_err(|err| map_timeout_err(err, limit))?;
+    }
+    Ok(tls)
 }
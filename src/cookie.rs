@@ -0,0 +1,827 @@
+use crate::date::parse_http_date;
+use crate::http_url::HttpUrl;
+use crate::{Error, HeaderMap};
+use alloc::sync::Arc;
+use core::fmt;
+use core::time::Duration;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// A store of cookies, shared between requests.
+///
+/// Requests that are given a jar with
+/// [`Request::with_cookie_jar`](struct.Request.html#method.with_cookie_jar)
+/// store the cookies set by the `Set-Cookie` headers of their
+/// responses in it, and send the matching cookies from it in a
+/// `Cookie` header. This also happens for each redirect that is
+/// followed, so cookies set by a redirect are sent to its location.
+///
+/// Cookies are handled as described in [RFC
+/// 6265](https://datatracker.ietf.org/doc/html/rfc6265):
+///
+/// - A cookie is only sent to the host that set it, or to its
+///   subdomains too if it was set with a `Domain` attribute. Cookies
+///   with a `Domain` that doesn't match the host setting them are
+///   ignored.
+/// - A cookie is only sent for paths under its `Path`, which by default
+///   is the directory of the URL that set it.
+/// - `Secure` cookies are only sent over (and accepted from) `https://`
+///   URLs.
+/// - Cookies expire at their `Expires` date, or after `Max-Age`
+///   seconds, which takes precedence. Cookies without either are kept
+///   until the jar is dropped or cleared.
+/// - A cookie replaces the one with the same name, domain and path, and
+///   an expired cookie removes it.
+///
+/// `HttpOnly` and `SameSite` are recorded, but don't change which
+/// cookies are sent, as there are no scripts or cross-site navigations
+/// here for them to apply to.
+///
+/// The jar can be saved to and loaded from a Netscape `cookies.txt`
+/// file (the format used by curl and wget), or with the `json` feature,
+/// a JSON file.
+///
+/// The jar is cheap to clone, and all clones share the same cookies.
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> Result<(), minireq::Error> {
+/// let jar = minireq::CookieJar::new();
+/// minireq::post("http://example.com/login")
+///     .with_cookie_jar(jar.clone())
+///     .send()?;
+/// // Sent with the session cookie set by the login response.
+/// let profile = minireq::get("http://example.com/profile")
+///     .with_cookie_jar(jar.clone())
+///     .send()?;
+///
+/// jar.save_netscape(std::fs::File::create("cookies.txt")?)?;
+/// # Ok(()) }
+/// ```
+#[derive(Clone)]
+pub struct CookieJar {
+    inner: Arc<Mutex<JarInner>>,
+}
+
+struct JarInner {
+    /// The unexpired cookies, in the order they were created.
+    cookies: Vec<Cookie>,
+    next_creation_index: u64,
+}
+
+/// A cookie stored in a [`CookieJar`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    domain: String,
+    path: String,
+    expires: Option<SystemTime>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+    host_only: bool,
+    /// Orders cookies with equally long paths in the `Cookie` header.
+    creation_index: u64,
+}
+
+/// The value of a cookie's `SameSite` attribute.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SameSite {
+    /// `SameSite=Strict`
+    Strict,
+    /// `SameSite=Lax`
+    Lax,
+    /// `SameSite=None`
+    None,
+}
+
+impl CookieJar {
+    /// Creates a new, empty cookie jar.
+    pub fn new() -> CookieJar {
+        CookieJar {
+            inner: Arc::new(Mutex::new(JarInner {
+                cookies: Vec::new(),
+                next_creation_index: 0,
+            })),
+        }
+    }
+
+    /// Stores the cookie from a `Set-Cookie` header value, as if it was
+    /// received in a response from `url`.
+    ///
+    /// Like cookies in responses, cookies that are malformed or that
+    /// `url` isn't allowed to set are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `url` can't be parsed.
+    pub fn set_cookie(&self, url: &str, set_cookie: &str) -> Result<(), Error> {
+        let url = HttpUrl::parse(url, None)?;
+        if let Some(cookie) = Cookie::parse(&url, set_cookie, SystemTime::now()) {
+            self.lock().insert(cookie);
+        }
+        Ok(())
+    }
+
+    /// Returns the cookies in the jar that haven't expired, in the
+    /// order they were created.
+    pub fn cookies(&self) -> Vec<Cookie> {
+        let mut inner = self.lock();
+        inner.evict_expired(SystemTime::now());
+        inner.cookies.clone()
+    }
+
+    /// Removes all the cookies from the jar.
+    pub fn clear(&self) {
+        self.lock().cookies.clear();
+    }
+
+    /// Writes the cookies to `writer` in the Netscape `cookies.txt`
+    /// format. Cookies without an expiry date are written with an
+    /// expiry of 0, and `HttpOnly` cookies have their lines prefixed
+    /// with `#HttpOnly_`, as curl does. The `SameSite` attribute isn't
+    /// saved, as the format has no field for it.
+    pub fn save_netscape<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        writeln!(writer, "# Netscape HTTP Cookie File")?;
+        for cookie in self.cookies() {
+            writeln!(
+                writer,
+                "{}{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                if cookie.http_only { "#HttpOnly_" } else { "" },
+                if cookie.host_only { "" } else { "." },
+                cookie.domain,
+                if cookie.host_only { "FALSE" } else { "TRUE" },
+                cookie.path,
+                if cookie.secure { "TRUE" } else { "FALSE" },
+                cookie.expires.map_or(0, unix_seconds),
+                cookie.name,
+                cookie.value,
+            )?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads a jar from a Netscape `cookies.txt` file, as written by
+    /// [`save_netscape`](#method.save_netscape), curl or wget. Cookies
+    /// that have expired are left out.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidCookieJar`](enum.Error.html#variant.InvalidCookieJar)
+    /// if a line is malformed.
+    pub fn load_netscape<R: Read>(reader: R) -> Result<CookieJar, Error> {
+        let jar = CookieJar::new();
+        let mut inner = jar.lock();
+        for (index, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+                Some(line) => (line, true),
+                None => (line.as_str(), false),
+            };
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |field: &str| {
+                Error::InvalidCookieJar(format!("invalid {} on line {}", field, index + 1))
+            };
+            let fields = line.split('\t').collect::<Vec<&str>>();
+            // Curl leaves out the tab before an empty value.
+            let (domain, include_subdomains, path, secure, expires, name, value) = match fields[..]
+            {
+                [domain, subdomains, path, secure, expires, name, value] => {
+                    (domain, subdomains, path, secure, expires, name, value)
+                }
+                [domain, subdomains, path, secure, expires, name] => {
+                    (domain, subdomains, path, secure, expires, name, "")
+                }
+                _ => return Err(invalid("amount of fields")),
+            };
+            let expires = match expires.trim().parse::<u64>() {
+                Ok(0) => None,
+                Ok(seconds) => Some(from_unix_seconds(seconds)),
+                Err(_) => return Err(invalid("expiry")),
+            };
+            inner.insert(Cookie {
+                name: name.to_string(),
+                value: value.to_string(),
+                domain: domain.trim_start_matches('.').to_ascii_lowercase(),
+                path: path.to_string(),
+                expires,
+                secure: parse_flag(secure).ok_or_else(|| invalid("secure flag"))?,
+                http_only,
+                same_site: None,
+                host_only: !parse_flag(include_subdomains)
+                    .ok_or_else(|| invalid("subdomain flag"))?,
+                creation_index: 0,
+            });
+        }
+        inner.evict_expired(SystemTime::now());
+        drop(inner);
+        Ok(jar)
+    }
+
+    /// Writes the cookies to `writer` as a JSON array, with an object
+    /// for each cookie. `expires` is in seconds since the Unix epoch,
+    /// or null for cookies without an expiry date.
+    #[cfg(feature = "json")]
+    pub fn save_json<W: Write>(&self, writer: W) -> Result<(), Error> {
+        let cookies = self
+            .cookies()
+            .into_iter()
+            .map(|cookie| {
+                serde_json::json!({
+                    "name": cookie.name,
+                    "value": cookie.value,
+                    "domain": cookie.domain,
+                    "path": cookie.path,
+                    "expires": cookie.expires.map(unix_seconds),
+                    "secure": cookie.secure,
+                    "http_only": cookie.http_only,
+                    "same_site": cookie.same_site.map(SameSite::as_str),
+                    "host_only": cookie.host_only,
+                })
+            })
+            .collect::<Vec<serde_json::Value>>();
+        serde_json::to_writer_pretty(writer, &cookies).map_err(Error::SerdeJsonError)
+    }
+
+    /// Reads a jar from JSON, as written by
+    /// [`save_json`](#method.save_json). Cookies that have expired are
+    /// left out.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::SerdeJsonError`](enum.Error.html#variant.SerdeJsonError)
+    /// if the input isn't JSON, and
+    /// [`Error::InvalidCookieJar`](enum.Error.html#variant.InvalidCookieJar)
+    /// if it doesn't describe cookies.
+    #[cfg(feature = "json")]
+    pub fn load_json<R: Read>(reader: R) -> Result<CookieJar, Error> {
+        use serde_json::Value;
+
+        let value: Value = serde_json::from_reader(reader).map_err(Error::SerdeJsonError)?;
+        let jar = CookieJar::new();
+        let mut inner = jar.lock();
+        let objects = value
+            .as_array()
+            .ok_or_else(|| Error::InvalidCookieJar("expected an array of cookies".to_string()))?;
+        for (index, object) in objects.iter().enumerate() {
+            let invalid = |field: &str| {
+                Error::InvalidCookieJar(format!("invalid {:?} in cookie {}", field, index))
+            };
+            let string = |field: &str| match object.get(field) {
+                Some(Value::String(value)) => Ok(value.clone()),
+                _ => Err(invalid(field)),
+            };
+            let flag = |field: &str| match object.get(field) {
+                Some(Value::Bool(value)) => Ok(*value),
+                None => Ok(false),
+                _ => Err(invalid(field)),
+            };
+            let expires = match object.get("expires") {
+                Some(Value::Null) | None => None,
+                Some(value) => Some(from_unix_seconds(
+                    value.as_u64().ok_or_else(|| invalid("expires"))?,
+                )),
+            };
+            let same_site = match object.get("same_site") {
+                Some(Value::Null) | None => None,
+                Some(Value::String(value)) => {
+                    Some(SameSite::parse(value).ok_or_else(|| invalid("same_site"))?)
+                }
+                _ => return Err(invalid("same_site")),
+            };
+            inner.insert(Cookie {
+                name: string("name")?,
+                value: string("value")?,
+                domain: string("domain")?.to_ascii_lowercase(),
+                path: string("path")?,
+                expires,
+                secure: flag("secure")?,
+                http_only: flag("http_only")?,
+                same_site,
+                host_only: flag("host_only")?,
+                creation_index: 0,
+            });
+        }
+        inner.evict_expired(SystemTime::now());
+        drop(inner);
+        Ok(jar)
+    }
+
+    /// Stores the cookies set by a response from `url`.
+    pub(crate) fn store_response(&self, url: &HttpUrl, headers: &HeaderMap) {
+        let now = SystemTime::now();
+        let mut inner = self.lock();
+        for set_cookie in headers.get_all("set-cookie") {
+            if let Some(cookie) = Cookie::parse(url, set_cookie, now) {
+                inner.insert(cookie);
+            }
+        }
+    }
+
+    /// Returns the value of the `Cookie` header to send to `url`, if
+    /// any cookies match it.
+    pub(crate) fn cookie_header(&self, url: &HttpUrl) -> Option<String> {
+        let now = SystemTime::now();
+        let mut inner = self.lock();
+        inner.evict_expired(now);
+        let host = url.host.to_ascii_lowercase();
+        let path = url_path(url);
+        let mut matching = (inner.cookies.iter())
+            .filter(|cookie| {
+                (!cookie.secure || url.https)
+                    && cookie.matches_domain(&host)
+                    && path_matches(path, &cookie.path)
+            })
+            .collect::<Vec<&Cookie>>();
+        if matching.is_empty() {
+            return None;
+        }
+        // Cookies with longer paths are listed first, see RFC 6265
+        // section 5.4.
+        matching.sort_by(|a, b| {
+            (b.path.len().cmp(&a.path.len())).then(a.creation_index.cmp(&b.creation_index))
+        });
+        let pairs = (matching.iter())
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect::<Vec<String>>();
+        Some(pairs.join("; "))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, JarInner> {
+        // Every change to the jar is a single push or removal, so a
+        // panic while it was locked can't have left it inconsistent.
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl JarInner {
+    /// Adds the cookie, replacing the one with the same name, domain
+    /// and path. An expired cookie only removes the one it replaces.
+    fn insert(&mut self, mut cookie: Cookie) {
+        let existing = self.cookies.iter().position(|other| {
+            other.name == cookie.name && other.domain == cookie.domain && other.path == cookie.path
+        });
+        let creation_index = match existing {
+            Some(index) => self.cookies.remove(index).creation_index,
+            None => {
+                self.next_creation_index += 1;
+                self.next_creation_index
+            }
+        };
+        if cookie.is_expired(SystemTime::now()) {
+            return;
+        }
+        cookie.creation_index = creation_index;
+        let position = self
+            .cookies
+            .iter()
+            .position(|other| other.creation_index > creation_index)
+            .unwrap_or(self.cookies.len());
+        self.cookies.insert(position, cookie);
+    }
+
+    fn evict_expired(&mut self, now: SystemTime) {
+        self.cookies.retain(|cookie| !cookie.is_expired(now));
+    }
+}
+
+impl Default for CookieJar {
+    fn default() -> CookieJar {
+        CookieJar::new()
+    }
+}
+
+impl PartialEq for CookieJar {
+    fn eq(&self, other: &CookieJar) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl Eq for CookieJar {}
+
+impl fmt::Debug for CookieJar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CookieJar")
+            .field("cookies", &self.lock().cookies.len())
+            .finish()
+    }
+}
+
+impl Cookie {
+    /// Parses a `Set-Cookie` header value received from `url`, as
+    /// described in RFC 6265 section 5.2 and 5.3. Returns None if the
+    /// cookie should be ignored.
+    fn parse(url: &HttpUrl, set_cookie: &str, now: SystemTime) -> Option<Cookie> {
+        let mut attributes = set_cookie.split(';');
+        let (name, value) = attributes.next()?.split_once('=')?;
+        let (name, value) = (name.trim(), value.trim());
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut max_age = None;
+        let mut expires = None;
+        let mut domain = None;
+        let mut path = None;
+        let mut secure = false;
+        let mut http_only = false;
+        let mut same_site = None;
+        for attribute in attributes {
+            let (key, value) = match attribute.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => (attribute.trim(), ""),
+            };
+            match key.to_ascii_lowercase().as_str() {
+                "expires" => expires = parse_http_date(value).or(expires),
+                "max-age" => max_age = parse_max_age(value, now).or(max_age),
+                "domain" if !value.is_empty() => {
+                    domain = Some(value.trim_start_matches('.').to_ascii_lowercase());
+                }
+                "path" => path = Some(value).filter(|path| path.starts_with('/')),
+                "secure" => secure = true,
+                "httponly" => http_only = true,
+                "samesite" => same_site = SameSite::parse(value),
+                _ => {}
+            }
+        }
+
+        let host = url.host.to_ascii_lowercase();
+        let host_only = match domain {
+            // A dotless domain would be a top-level domain, which only
+            // that exact host (eg. localhost) can set cookies for.
+            Some(ref domain) if !domain.contains('.') && *domain == host => true,
+            Some(ref domain) if domain_matches(&host, domain) && domain.contains('.') => false,
+            Some(_) => return None,
+            None => true,
+        };
+        if secure && !url.https {
+            return None;
+        }
+        Some(Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            domain: domain.unwrap_or(host),
+            path: path.map_or_else(|| default_path(url_path(url)), str::to_string),
+            expires: max_age.or(expires),
+            secure,
+            http_only,
+            same_site,
+            host_only,
+            creation_index: 0,
+        })
+    }
+
+    /// Returns the name of the cookie.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the value of the cookie.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Returns the domain the cookie is sent to, in lowercase and
+    /// without a leading dot.
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// Returns the path the cookie is sent for, along with the paths
+    /// under it.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns when the cookie expires, or None if it's kept until the
+    /// jar is dropped.
+    pub fn expires(&self) -> Option<SystemTime> {
+        self.expires
+    }
+
+    /// Returns true if the cookie is only sent over `https://`.
+    pub fn is_secure(&self) -> bool {
+        self.secure
+    }
+
+    /// Returns true if the cookie had the `HttpOnly` attribute.
+    pub fn is_http_only(&self) -> bool {
+        self.http_only
+    }
+
+    /// Returns the cookie's `SameSite` attribute, if it had one.
+    pub fn same_site(&self) -> Option<SameSite> {
+        self.same_site
+    }
+
+    /// Returns true if the cookie is only sent to its exact domain,
+    /// ie. it was set without a `Domain` attribute. Otherwise it's sent
+    /// to subdomains too.
+    pub fn is_host_only(&self) -> bool {
+        self.host_only
+    }
+
+    fn is_expired(&self, now: SystemTime) -> bool {
+        matches!(self.expires, Some(expires) if expires <= now)
+    }
+
+    fn matches_domain(&self, host: &str) -> bool {
+        if self.host_only {
+            self.domain == host
+        } else {
+            domain_matches(host, &self.domain)
+        }
+    }
+}
+
+impl SameSite {
+    fn parse(value: &str) -> Option<SameSite> {
+        match value.to_ascii_lowercase().as_str() {
+            "strict" => Some(SameSite::Strict),
+            "lax" => Some(SameSite::Lax),
+            "none" => Some(SameSite::None),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "json")]
+    fn as_str(self) -> &'static str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+}
+
+/// Parses a `Max-Age` attribute into the time the cookie expires at.
+/// Zero and negative ages expire the cookie immediately.
+fn parse_max_age(value: &str, now: SystemTime) -> Option<SystemTime> {
+    if !value.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+        return None;
+    }
+    let seconds = value.parse::<i64>().ok()?;
+    if seconds <= 0 {
+        return Some(SystemTime::UNIX_EPOCH);
+    }
+    // Clamped so that adding it to the current time can't overflow.
+    let age = Duration::from_secs((seconds as u64).min(u64::from(u32::MAX)));
+    Some(now + age)
+}
+
+/// Returns true if `host` is `domain` or one of its subdomains. IP
+/// addresses only match themselves.
+fn domain_matches(host: &str, domain: &str) -> bool {
+    if host == domain {
+        return true;
+    }
+    let is_ip = host.starts_with('[') || host.parse::<IpAddr>().is_ok();
+    !is_ip
+        && host.len() > domain.len()
+        && host.ends_with(domain)
+        && host.as_bytes()[host.len() - domain.len() - 1] == b'.'
+}
+
+/// Returns true if a cookie with the path `cookie_path` is sent for
+/// requests to `path`, ie. `cookie_path` is `path` or a directory
+/// containing it.
+fn path_matches(path: &str, cookie_path: &str) -> bool {
+    match path.strip_prefix(cookie_path) {
+        Some(rest) => rest.is_empty() || cookie_path.ends_with('/') || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+/// Returns the path of the URL, without the query.
+fn url_path(url: &HttpUrl) -> &str {
+    match url.path_and_query.split_once('?') {
+        Some((path, _)) => path,
+        None => &url.path_and_query,
+    }
+}
+
+/// Returns the path of cookies set without a `Path` attribute, which
+/// is the "directory" of the request's path.
+fn default_path(path: &str) -> String {
+    match path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(last_slash) => path[..last_slash].to_string(),
+    }
+}
+
+fn parse_flag(flag: &str) -> Option<bool> {
+    match flag.trim() {
+        "TRUE" => Some(true),
+        "FALSE" => Some(false),
+        _ => None,
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    let since_epoch = time.duration_since(SystemTime::UNIX_EPOCH);
+    since_epoch.map_or(0, |duration| duration.as_secs())
+}
+
+fn from_unix_seconds(seconds: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{default_path, path_matches, CookieJar, SameSite};
+    use crate::http_url::HttpUrl;
+    use std::time::{Duration, SystemTime};
+
+    fn header(jar: &CookieJar, url: &str) -> Option<String> {
+        jar.cookie_header(&HttpUrl::parse(url, None).unwrap())
+    }
+
+    #[test]
+    fn attributes() {
+        let jar = CookieJar::new();
+        jar.set_cookie(
+            "https://www.example.com/a/b",
+            "id=a3fWa; Domain=.Example.com; Path=/; Max-Age=60; Secure; HttpOnly; SameSite=Lax",
+        )
+        .unwrap();
+        let cookies = jar.cookies();
+        let cookie = &cookies[0];
+        assert_eq!((cookie.name(), cookie.value()), ("id", "a3fWa"));
+        assert_eq!(cookie.domain(), "example.com");
+        assert_eq!(cookie.path(), "/");
+        assert!(cookie.expires().unwrap() > SystemTime::now() + Duration::from_secs(50));
+        assert!(cookie.is_secure() && cookie.is_http_only() && !cookie.is_host_only());
+        assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+    }
+
+    #[test]
+    fn domain_matching() {
+        let jar = CookieJar::new();
+        jar.set_cookie("http://example.com/", "host=1").unwrap();
+        jar.set_cookie("http://example.com/", "domain=2; Domain=example.com")
+            .unwrap();
+        // Not allowed to be set by example.com.
+        jar.set_cookie("http://example.com/", "other=3; Domain=example.org")
+            .unwrap();
+        jar.set_cookie("http://example.com/", "tld=4; Domain=com")
+            .unwrap();
+        jar.set_cookie("http://example.com/", "sub=5; Domain=www.example.com")
+            .unwrap();
+
+        assert_eq!(
+            header(&jar, "http://example.com/").as_deref(),
+            Some("host=1; domain=2")
+        );
+        assert_eq!(
+            header(&jar, "http://www.example.com/").as_deref(),
+            Some("domain=2")
+        );
+        assert_eq!(header(&jar, "http://notexample.com/"), None);
+    }
+
+    #[test]
+    fn path_matching() {
+        assert_eq!(default_path("/"), "/");
+        assert_eq!(default_path("/login"), "/");
+        assert_eq!(default_path("/account/login"), "/account");
+        assert!(path_matches("/account", "/account"));
+        assert!(path_matches("/account/settings", "/account"));
+        assert!(path_matches("/account/settings", "/account/"));
+        assert!(!path_matches("/accounts", "/account"));
+        assert!(!path_matches("/", "/account"));
+
+        let jar = CookieJar::new();
+        jar.set_cookie("http://example.com/account/login", "a=1")
+            .unwrap();
+        jar.set_cookie("http://example.com/", "b=2").unwrap();
+        jar.set_cookie("http://example.com/", "c=3; Path=/account/settings")
+            .unwrap();
+        assert_eq!(
+            header(&jar, "http://example.com/account/settings?tab=1").as_deref(),
+            Some("c=3; a=1; b=2")
+        );
+        assert_eq!(header(&jar, "http://example.com/").as_deref(), Some("b=2"));
+    }
+
+    #[test]
+    fn secure_cookies() {
+        let jar = CookieJar::new();
+        jar.set_cookie("http://example.com/", "insecure=1; Secure")
+            .unwrap();
+        jar.set_cookie("https://example.com/", "secure=2; Secure")
+            .unwrap();
+        assert_eq!(header(&jar, "http://example.com/"), None);
+        assert_eq!(
+            header(&jar, "https://example.com/").as_deref(),
+            Some("secure=2")
+        );
+    }
+
+    #[test]
+    fn replacing_and_expiring() {
+        let jar = CookieJar::new();
+        jar.set_cookie("http://example.com/", "a=1").unwrap();
+        jar.set_cookie("http://example.com/", "b=2").unwrap();
+        jar.set_cookie("http://example.com/", "a=3").unwrap();
+        // Replaced cookies keep their place.
+        assert_eq!(
+            header(&jar, "http://example.com/").as_deref(),
+            Some("a=3; b=2")
+        );
+
+        jar.set_cookie("http://example.com/", "a=; Max-Age=0")
+            .unwrap();
+        jar.set_cookie(
+            "http://example.com/",
+            "b=; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+        )
+        .unwrap();
+        // Max-Age takes precedence over Expires.
+        jar.set_cookie(
+            "http://example.com/",
+            "c=4; Max-Age=60; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+        )
+        .unwrap();
+        assert_eq!(header(&jar, "http://example.com/").as_deref(), Some("c=4"));
+    }
+
+    #[test]
+    fn malformed_cookies_are_ignored() {
+        let jar = CookieJar::new();
+        jar.set_cookie("http://example.com/", "novalue").unwrap();
+        jar.set_cookie("http://example.com/", "=nameless").unwrap();
+        jar.set_cookie("http://example.com/", "a=1; Max-Age=soon; Path=relative")
+            .unwrap();
+        let cookies = jar.cookies();
+        assert_eq!(cookies.len(), 1);
+        assert_eq!(cookies[0].expires(), None);
+        assert_eq!(cookies[0].path(), "/");
+    }
+
+    #[test]
+    fn netscape_format() {
+        let jar = CookieJar::new();
+        jar.set_cookie("http://example.com/", "session=1; HttpOnly")
+            .unwrap();
+        jar.set_cookie(
+            "https://example.com/",
+            "persistent=2; Domain=example.com; Path=/a; Secure; Expires=Fri, 01 Jan 2100 00:00:00 GMT",
+        )
+        .unwrap();
+        let mut saved = Vec::new();
+        jar.save_netscape(&mut saved).unwrap();
+        assert_eq!(
+            String::from_utf8(saved.clone()).unwrap(),
+            "# Netscape HTTP Cookie File\n\
+             #HttpOnly_example.com\tFALSE\t/\tFALSE\t0\tsession\t1\n\
+             .example.com\tTRUE\t/a\tTRUE\t4102444800\tpersistent\t2\n"
+        );
+
+        let loaded = CookieJar::load_netscape(&saved[..]).unwrap();
+        assert_eq!(loaded.cookies(), jar.cookies());
+
+        let expired = "example.com\tFALSE\t/\tFALSE\t1\told\tvalue\n\
+                       example.com\tFALSE\t/\tFALSE\t0\tempty\n";
+        let loaded = CookieJar::load_netscape(expired.as_bytes()).unwrap();
+        assert_eq!(
+            header(&loaded, "http://example.com/").as_deref(),
+            Some("empty=")
+        );
+
+        let malformed = "example.com\tMAYBE\t/\tFALSE\t0\tname\tvalue\n";
+        assert!(CookieJar::load_netscape(malformed.as_bytes()).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn json_format() {
+        let jar = CookieJar::new();
+        jar.set_cookie("http://example.com/", "a=1; SameSite=Strict")
+            .unwrap();
+        jar.set_cookie(
+            "http://example.com/",
+            "b=2; Max-Age=3600; Domain=example.com",
+        )
+        .unwrap();
+        let mut saved = Vec::new();
+        jar.save_json(&mut saved).unwrap();
+
+        let mut cookies = jar.cookies();
+        // Only whole seconds are saved.
+        let expires = cookies[1].expires.unwrap();
+        let since_epoch = expires.duration_since(SystemTime::UNIX_EPOCH).unwrap();
+        cookies[1].expires =
+            Some(expires - Duration::from_nanos(since_epoch.subsec_nanos().into()));
+        assert_eq!(CookieJar::load_json(&saved[..]).unwrap().cookies(), cookies);
+
+        assert!(CookieJar::load_json(&b"{}"[..]).is_err());
+        assert!(CookieJar::load_json(&b"[{\"name\": 1}]"[..]).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+//! Parsing of the date formats used in HTTP headers, see [RFC 7231
+//! section 7.1.1.1](https://datatracker.ietf.org/doc/html/rfc7231#section-7.1.1.1).
+
+use std::time::{Duration, SystemTime};
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// Parses an HTTP-date in any of the three formats that recipients
+/// are required to accept:
+///
+/// - `Sun, 06 Nov 1994 08:49:37 GMT` (IMF-fixdate)
+/// - `Sunday, 06-Nov-94 08:49:37 GMT` (obsolete RFC 850 format)
+/// - `Sun Nov  6 08:49:37 1994` (ANSI C's `asctime()` format)
+///
+/// The day of the week isn't checked.
+pub(crate) fn parse_http_date(date: &str) -> Option<SystemTime> {
+    let date = date.trim();
+    let (_weekday, rest) = date.split_once([',', ' '])?;
+    let fields = rest.split_whitespace().collect::<Vec<&str>>();
+    let (year, month, day, time) = match fields[..] {
+        // IMF-fixdate: 06 Nov 1994 08:49:37 GMT
+        [day, month, year, time, "GMT"] if date.contains(',') => {
+            (parse_year(year)?, month, day.parse().ok()?, time)
+        }
+        // RFC 850: 06-Nov-94 08:49:37 GMT
+        [day_month_year, time, "GMT"] => {
+            let mut parts = day_month_year.split('-');
+            let day = parts.next()?.parse().ok()?;
+            let month = parts.next()?;
+            let year = parse_year(parts.next()?)?;
+            (year, month, day, time)
+        }
+        // asctime: Nov  6 08:49:37 1994
+        [month, day, time, year] if !date.contains(',') => {
+            (parse_year(year)?, month, day.parse().ok()?, time)
+        }
+        _ => return None,
+    };
+    let month = MONTHS
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(month))? as u32
+        + 1;
+    let mut time = time.split(':').map(|part| part.parse::<u64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    if time.next().is_some()
+        || !(1..=31).contains(&day)
+        || year < 1970
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    let days = days_since_epoch(year, month, day);
+    let seconds = days * 86400 + hour * 3600 + minute * 60 + second;
+    SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(seconds))
+}
+
+/// Parses a four digit year, or a two digit one from the RFC 850
+/// format, which is interpreted as the closest matching year to now.
+fn parse_year(year: &str) -> Option<u64> {
+    let parsed = year.parse::<u64>().ok()?;
+    match year.len() {
+        4 => Some(parsed),
+        // RFC 7231 says two digit years more than 50 years in the
+        // future should be interpreted as being in the past, which
+        // makes 2000-2069 and 1970-1999 a close enough approximation.
+        2 if parsed < 70 => Some(2000 + parsed),
+        2 => Some(1900 + parsed),
+        _ => None,
+    }
+}
+
+/// Returns the number of days from 1970-01-01 to the given date, see
+/// <http://howardhinnant.github.io/date_algorithms.html#days_from_civil>.
+fn days_since_epoch(year: u64, month: u32, day: u32) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let month = u64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + u64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_http_date;
+    use std::time::{Duration, SystemTime};
+
+    fn at(seconds: u64) -> Option<SystemTime> {
+        Some(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+    }
+
+    #[test]
+    fn formats() {
+        // The examples from RFC 7231.
+        let expected = at(784111777);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), expected);
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), expected);
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), expected);
+    }
+
+    #[test]
+    fn leap_years() {
+        assert_eq!(parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT"), at(0));
+        assert_eq!(
+            parse_http_date("Tue, 29 Feb 2000 12:00:00 GMT"),
+            at(951825600)
+        );
+        assert_eq!(
+            parse_http_date("Fri, 01 Mar 2024 00:00:00 GMT"),
+            at(1709251200)
+        );
+    }
+
+    #[test]
+    fn invalid_dates() {
+        assert_eq!(parse_http_date(""), None);
+        assert_eq!(parse_http_date("120"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 PST"), None);
+        assert_eq!(parse_http_date("Sun, 06 Foo 1994 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 24:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49 GMT"), None);
+    }
+}
@@ -0,0 +1,356 @@
+use crate::HeaderMap;
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+
+/// The most decoded bytes that are held at once, however much the
+/// codings expand the body.
+const OUTPUT_BUFFER_LENGTH: usize = 16 * 1024;
+
+/// The content codings that can be decoded with the enabled features,
+/// in the order they're listed in `Accept-Encoding`.
+const SUPPORTED_CODINGS: &[&str] = &[
+    #[cfg(feature = "gzip")]
+    "gzip",
+    #[cfg(feature = "deflate")]
+    "deflate",
+    #[cfg(feature = "brotli")]
+    "br",
+    #[cfg(feature = "zstd")]
+    "zstd",
+];
+
+/// Returns the value of the `Accept-Encoding` header sent by requests
+/// that decompress their responses, if any codings are supported.
+pub(crate) fn accept_encoding() -> Option<String> {
+    if SUPPORTED_CODINGS.is_empty() {
+        None
+    } else {
+        Some(SUPPORTED_CODINGS.join(", "))
+    }
+}
+
+/// Decodes a response body with the content codings listed in its
+/// `Content-Encoding` headers.
+///
+/// The decoders are chained readers, the innermost of which reads the
+/// received bytes given with [`push_input`](Decoder::push_input). The
+/// body is decoded as it's read, into a buffer of a fixed size, so a
+/// small body that decodes into a huge one is never held in memory at
+/// once.
+pub(crate) struct Decoder {
+    /// The decoder for the coding applied first, which reads from the
+    /// decoder for the coding applied after it, and so on.
+    reader: Box<dyn Read + Send>,
+    input: Arc<Mutex<Input>>,
+    output: Vec<u8>,
+    output_length: usize,
+    position: usize,
+    decoded_length: usize,
+    finished: bool,
+}
+
+impl Decoder {
+    /// Returns a decoder for the codings in `headers`, or None if the
+    /// body isn't encoded, or is encoded with a coding that isn't
+    /// supported (in which case it's left as-is).
+    pub(crate) fn new(headers: &HeaderMap) -> Option<Decoder> {
+        let input = Arc::new(Mutex::new(Input::default()));
+        let mut reader: Box<dyn Read + Send> = Box::new(InputReader(input.clone()));
+        let codings: Vec<String> = headers
+            .get_all("content-encoding")
+            .flat_map(|value| value.split(','))
+            .map(|coding| coding.trim().to_ascii_lowercase())
+            .filter(|coding| !coding.is_empty() && coding != "identity")
+            .collect();
+        if codings.is_empty() {
+            return None;
+        }
+        // The codings are listed in the order they were applied, so the
+        // last one is undone first, by the reader of the received bytes.
+        for coding in codings.iter().rev() {
+            reader = decoding_reader(coding, reader)?;
+        }
+        Some(Decoder {
+            reader,
+            input,
+            output: vec![0; OUTPUT_BUFFER_LENGTH],
+            output_length: 0,
+            position: 0,
+            decoded_length: 0,
+            finished: false,
+        })
+    }
+
+    /// Returns the next decoded byte, along with the amount of decoded
+    /// bytes that are ready (including this one).
+    pub(crate) fn next_byte(&mut self) -> Option<(u8, usize)> {
+        if self.position >= self.output_length {
+            return None;
+        }
+        let byte = self.output[self.position];
+        let ready = self.output_length - self.position;
+        self.position += 1;
+        Some((byte, ready))
+    }
+
+    /// Returns true once the whole body has been decoded, or decoding
+    /// has failed.
+    pub(crate) fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Returns the length of the decoded body so far.
+    pub(crate) fn decoded_length(&self) -> usize {
+        self.decoded_length
+    }
+
+    /// Returns true if all of the received bytes have been read by the
+    /// decoders, and they can't go on without more.
+    pub(crate) fn needs_input(&self) -> bool {
+        let input = lock(&self.input);
+        input.position == input.bytes.len() && !input.ended
+    }
+
+    /// Gives the decoders the next piece of the body, which is the last
+    /// one if `ended` is true. Should only be called when
+    /// [`needs_input`](Decoder::needs_input) returns true.
+    pub(crate) fn push_input(&mut self, bytes: Vec<u8>, ended: bool) {
+        let mut input = lock(&self.input);
+        input.bytes = bytes;
+        input.position = 0;
+        input.ended = ended;
+    }
+
+    /// Decodes as much of the body as fits in the output buffer. Should
+    /// only be called once all the previously decoded bytes have been
+    /// read. Decodes nothing if the decoders need more input first.
+    pub(crate) fn decode(&mut self) -> io::Result<()> {
+        self.output_length = 0;
+        self.position = 0;
+        match self.reader.read(&mut self.output) {
+            Ok(0) => {
+                self.finished = true;
+                Ok(())
+            }
+            Ok(length) => {
+                self.output_length = length;
+                self.decoded_length += length;
+                Ok(())
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(()),
+            Err(err) => {
+                self.finished = true;
+                Err(err)
+            }
+        }
+    }
+}
+
+/// Wraps `reader` in a reader that undoes `coding`, if it's supported.
+// `reader` is unused when no decompression features are enabled.
+#[allow(unused_variables)]
+fn decoding_reader(coding: &str, reader: Box<dyn Read + Send>) -> Option<Box<dyn Read + Send>> {
+    match coding {
+        #[cfg(feature = "gzip")]
+        "gzip" | "x-gzip" => Some(Box::new(flate2::read::GzDecoder::new(reader))),
+        #[cfg(feature = "deflate")]
+        "deflate" => Some(Box::new(flate2::read::ZlibDecoder::new(reader))),
+        #[cfg(feature = "brotli")]
+        "br" => Some(Box::new(brotli_decompressor::Decompressor::new(
+            reader, 4096,
+        ))),
+        #[cfg(feature = "zstd")]
+        "zstd" => match zstd::stream::read::Decoder::new(reader) {
+            Ok(decoder) => Some(Box::new(decoder)),
+            Err(_) => None,
+        },
+        _ => None,
+    }
+}
+
+/// The piece of the encoded body that the decoders are reading.
+#[derive(Default)]
+struct Input {
+    bytes: Vec<u8>,
+    position: usize,
+    /// True if `bytes` is the last piece of the body.
+    ended: bool,
+}
+
+fn lock(input: &Mutex<Input>) -> std::sync::MutexGuard<'_, Input> {
+    input.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Reads the current piece of the encoded body. When it has all been
+/// read, the read fails with `WouldBlock` until the next piece is
+/// pushed, which the decoders pass on without losing their state.
+struct InputReader(Arc<Mutex<Input>>);
+
+impl Read for InputReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut input = lock(&self.0);
+        let available = &input.bytes[input.position..];
+        if available.is_empty() && !input.ended {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let length = available.len().min(buf.len());
+        buf[..length].copy_from_slice(&available[..length]);
+        input.position += length;
+        Ok(length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Decoder;
+    use crate::HeaderMap;
+    #[cfg(any(
+        feature = "gzip",
+        feature = "deflate",
+        feature = "brotli",
+        feature = "zstd"
+    ))]
+    use {super::OUTPUT_BUFFER_LENGTH, std::io};
+
+    /// Decodes `encoded` with the codings in `content_encoding`, giving
+    /// it to the decoder in small pieces.
+    #[cfg(any(
+        feature = "gzip",
+        feature = "deflate",
+        feature = "brotli",
+        feature = "zstd"
+    ))]
+    fn decode(content_encoding: &str, encoded: &[u8]) -> io::Result<Vec<u8>> {
+        let mut headers = HeaderMap::new();
+        headers.append("Content-Encoding", content_encoding);
+        let mut decoder = Decoder::new(&headers).unwrap();
+        let mut pieces = encoded.chunks(7);
+        let mut output = Vec::new();
+        loop {
+            while let Some((byte, ready)) = decoder.next_byte() {
+                assert!(ready <= OUTPUT_BUFFER_LENGTH);
+                output.push(byte);
+            }
+            if decoder.is_finished() {
+                assert_eq!(decoder.decoded_length(), output.len());
+                return Ok(output);
+            }
+            if decoder.needs_input() {
+                match pieces.next() {
+                    Some(piece) => decoder.push_input(piece.to_vec(), false),
+                    None => decoder.push_input(Vec::new(), true),
+                }
+            }
+            decoder.decode()?;
+        }
+    }
+
+    /// Checks that `encoded` decodes into `decoded`, and that it fails
+    /// to decode when cut short.
+    #[cfg(any(
+        feature = "gzip",
+        feature = "deflate",
+        feature = "brotli",
+        feature = "zstd"
+    ))]
+    fn check_round_trip(content_encoding: &str, encoded: &[u8], decoded: &[u8]) {
+        assert_eq!(decode(content_encoding, encoded).unwrap(), decoded);
+        let truncated = &encoded[..encoded.len() - 1];
+        assert!(decode(content_encoding, truncated).is_err());
+    }
+
+    #[cfg(any(feature = "gzip", feature = "deflate"))]
+    fn flate2_encode<W: io::Write>(mut encoder: W, data: &[u8]) -> W {
+        encoder.write_all(data).unwrap();
+        encoder
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn gzip() {
+        use flate2::{write::GzEncoder, Compression};
+        let data = b"hello hello hello";
+        let encoded = flate2_encode(GzEncoder::new(Vec::new(), Compression::default()), data);
+        check_round_trip("x-gzip", &encoded.finish().unwrap(), data);
+    }
+
+    #[test]
+    #[cfg(feature = "deflate")]
+    fn deflate() {
+        use flate2::{write::ZlibEncoder, Compression};
+        let data = b"hello hello hello";
+        let encoded = flate2_encode(ZlibEncoder::new(Vec::new(), Compression::default()), data);
+        check_round_trip("deflate", &encoded.finish().unwrap(), data);
+    }
+
+    #[test]
+    #[cfg(feature = "brotli")]
+    fn brotli() {
+        // "hello\n" in a single uncompressed meta-block.
+        check_round_trip("br", b"\x8f\x02\x80hello\n\x03", b"hello\n");
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn zstd() {
+        let data = b"hello hello hello";
+        let encoded = zstd::stream::encode_all(&data[..], 0).unwrap();
+        check_round_trip("zstd", &encoded, data);
+    }
+
+    #[test]
+    #[cfg(all(feature = "gzip", feature = "deflate"))]
+    fn stacked_codings() {
+        use flate2::write::{GzEncoder, ZlibEncoder};
+        use flate2::Compression;
+        // Content-Encoding: deflate, gzip means deflate was applied
+        // first, and has to be undone last.
+        let zlib = flate2_encode(ZlibEncoder::new(Vec::new(), Compression::default()), b"hi");
+        let gzip = GzEncoder::new(Vec::new(), Compression::default());
+        let encoded = flate2_encode(gzip, &zlib.finish().unwrap())
+            .finish()
+            .unwrap();
+        check_round_trip("deflate, GZIP", &encoded, b"hi");
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn bounded_output() {
+        use flate2::{write::GzEncoder, Compression};
+        // 64 MiB of zeros, compressed twice, is only a few hundred
+        // bytes, and is decoded a buffer at a time.
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::best());
+        for _ in 0..64 {
+            io::Write::write_all(&mut gzip, &[0; 1024 * 1024]).unwrap();
+        }
+        let once = gzip.finish().unwrap();
+        let twice = flate2_encode(GzEncoder::new(Vec::new(), Compression::best()), &once);
+        let encoded = twice.finish().unwrap();
+        assert!(encoded.len() < 1024);
+
+        let mut headers = HeaderMap::new();
+        headers.append("Content-Encoding", "gzip, gzip");
+        let mut decoder = Decoder::new(&headers).unwrap();
+        decoder.push_input(encoded, true);
+        while !decoder.is_finished() {
+            decoder.decode().unwrap();
+            let mut ready = 0;
+            while let Some((byte, _)) = decoder.next_byte() {
+                assert_eq!(byte, 0);
+                ready += 1;
+            }
+            assert!(ready <= OUTPUT_BUFFER_LENGTH);
+        }
+        assert_eq!(decoder.decoded_length(), 64 * 1024 * 1024);
+    }
+
+    #[test]
+    fn unsupported_codings_are_left_alone() {
+        let mut headers = HeaderMap::new();
+        assert!(Decoder::new(&headers).is_none());
+        headers.append("Content-Encoding", "identity");
+        assert!(Decoder::new(&headers).is_none());
+        headers.append("Content-Encoding", "gzip, compress");
+        assert!(Decoder::new(&headers).is_none());
+    }
+}
@@ -0,0 +1,452 @@
+//! HTTP Digest authentication, as described in [RFC
+//! 7616](https://datatracker.ietf.org/doc/html/rfc7616).
+
+use alloc::sync::Arc;
+use core::fmt::{self, Write};
+use md5::{Digest, Md5};
+use sha2::Sha256;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// The credentials set with
+/// [`Request::with_digest_auth`](struct.Request.html#method.with_digest_auth),
+/// along with the last challenge received for them, which is answered
+/// again for the following requests without waiting for a new one.
+#[derive(Clone)]
+pub(crate) struct DigestAuth {
+    username: String,
+    password: String,
+    session: Arc<Mutex<Option<DigestSession>>>,
+}
+
+struct DigestSession {
+    challenge: Challenge,
+    /// How many requests have been sent with the challenge's nonce.
+    nonce_count: u32,
+}
+
+impl DigestAuth {
+    pub(crate) fn new(username: String, password: String) -> DigestAuth {
+        DigestAuth {
+            username,
+            password,
+            session: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Returns the `Authorization` header for a request, if an earlier
+    /// response has challenged these credentials.
+    pub(crate) fn preemptive(&self, method: &str, uri: &str) -> Option<String> {
+        let mut session = self.lock();
+        let session = session.as_mut()?;
+        session.nonce_count = session.nonce_count.checked_add(1)?;
+        let challenge = &session.challenge;
+        Some(challenge.authorization(
+            &self.username,
+            &self.password,
+            method,
+            uri,
+            session.nonce_count,
+        ))
+    }
+
+    /// Answers the first supported challenge of a 401 response's
+    /// `WWW-Authenticate` headers, and keeps it for the following
+    /// requests. Returns None if there are no supported challenges.
+    pub(crate) fn respond<'a, I>(&self, challenges: I, method: &str, uri: &str) -> Option<String>
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let challenge = Challenge::select(challenges)?;
+        let authorization = challenge.authorization(&self.username, &self.password, method, uri, 1);
+        *self.lock() = Some(DigestSession {
+            challenge,
+            nonce_count: 1,
+        });
+        Some(authorization)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<DigestSession>> {
+        // The session is replaced as a whole, so a panic while it was
+        // locked can't have left it half-updated.
+        self.session.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl PartialEq for DigestAuth {
+    fn eq(&self, other: &DigestAuth) -> bool {
+        self.username == other.username
+            && self.password == other.password
+            && Arc::ptr_eq(&self.session, &other.session)
+    }
+}
+
+impl Eq for DigestAuth {}
+
+impl fmt::Debug for DigestAuth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DigestAuth")
+            .field("username", &self.username)
+            .finish()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Algorithm {
+    Md5,
+    Md5Sess,
+    Sha256,
+    Sha256Sess,
+}
+
+impl Algorithm {
+    fn parse(name: &str) -> Option<Algorithm> {
+        match name.to_ascii_uppercase().as_str() {
+            "MD5" => Some(Algorithm::Md5),
+            "MD5-SESS" => Some(Algorithm::Md5Sess),
+            "SHA-256" => Some(Algorithm::Sha256),
+            "SHA-256-SESS" => Some(Algorithm::Sha256Sess),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Algorithm::Md5 => "MD5",
+            Algorithm::Md5Sess => "MD5-sess",
+            Algorithm::Sha256 => "SHA-256",
+            Algorithm::Sha256Sess => "SHA-256-sess",
+        }
+    }
+
+    /// Returns the hash of `data` in lowercase hex.
+    fn hash(self, data: &str) -> String {
+        match self {
+            Algorithm::Md5 | Algorithm::Md5Sess => hex(&Md5::digest(data)),
+            Algorithm::Sha256 | Algorithm::Sha256Sess => hex(&Sha256::digest(data)),
+        }
+    }
+}
+
+/// A `Digest` challenge from a `WWW-Authenticate` or
+/// `Proxy-Authenticate` header.
+#[derive(Clone, Debug)]
+pub(crate) struct Challenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    algorithm: Algorithm,
+    /// Whether the challenge has a `qop` that includes `auth`. Without
+    /// a `qop`, the response is computed as in RFC 2069.
+    qop_auth: bool,
+    userhash: bool,
+}
+
+impl Challenge {
+    /// Returns the first `Digest` challenge with a supported algorithm
+    /// and quality of protection, from a list of header values.
+    pub(crate) fn select<'a, I>(header_values: I) -> Option<Challenge>
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        header_values
+            .flat_map(parse_challenges)
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("digest"))
+            .find_map(|(_, params)| Challenge::from_params(&params))
+    }
+
+    fn from_params(params: &[(String, String)]) -> Option<Challenge> {
+        let param = |name: &str| {
+            (params.iter())
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        };
+        let algorithm = match param("algorithm") {
+            Some(algorithm) => Algorithm::parse(algorithm)?,
+            None => Algorithm::Md5,
+        };
+        let qop_auth = match param("qop") {
+            Some(qop) => {
+                let mut options = qop.split(',').map(str::trim);
+                // Only auth-int is offered, which isn't supported.
+                if !options.any(|option| option.eq_ignore_ascii_case("auth")) {
+                    return None;
+                }
+                true
+            }
+            None => false,
+        };
+        Some(Challenge {
+            realm: param("realm").unwrap_or("").to_string(),
+            nonce: param("nonce")?.to_string(),
+            opaque: param("opaque").map(str::to_string),
+            algorithm,
+            qop_auth,
+            // `Option::is_some_and` needs Rust 1.70.
+            #[allow(clippy::unnecessary_map_or)]
+            userhash: param("userhash")
+                .map_or(false, |userhash| userhash.eq_ignore_ascii_case("true")),
+        })
+    }
+
+    /// Returns the value of the `Authorization` (or
+    /// `Proxy-Authorization`) header answering this challenge.
+    pub(crate) fn authorization(
+        &self,
+        username: &str,
+        password: &str,
+        method: &str,
+        uri: &str,
+        nonce_count: u32,
+    ) -> String {
+        self.authorization_with_cnonce(username, password, method, uri, nonce_count, &cnonce())
+    }
+
+    fn authorization_with_cnonce(
+        &self,
+        username: &str,
+        password: &str,
+        method: &str,
+        uri: &str,
+        nonce_count: u32,
+        cnonce: &str,
+    ) -> String {
+        let algorithm = self.algorithm;
+        let mut ha1 = algorithm.hash(&format!("{}:{}:{}", username, self.realm, password));
+        if matches!(algorithm, Algorithm::Md5Sess | Algorithm::Sha256Sess) {
+            ha1 = algorithm.hash(&format!("{}:{}:{}", ha1, self.nonce, cnonce));
+        }
+        let ha2 = algorithm.hash(&format!("{}:{}", method, uri));
+        let nc = format!("{:08x}", nonce_count);
+        let response = if self.qop_auth {
+            algorithm.hash(&format!(
+                "{}:{}:{}:{}:auth:{}",
+                ha1, self.nonce, nc, cnonce, ha2
+            ))
+        } else {
+            algorithm.hash(&format!("{}:{}:{}", ha1, self.nonce, ha2))
+        };
+
+        let username = if self.userhash {
+            algorithm.hash(&format!("{}:{}", username, self.realm))
+        } else {
+            username.to_string()
+        };
+        let mut header = String::from("Digest ");
+        write!(header, "username={}", quote(&username)).unwrap();
+        write!(header, ", realm={}", quote(&self.realm)).unwrap();
+        write!(header, ", uri={}", quote(uri)).unwrap();
+        write!(header, ", algorithm={}", algorithm.name()).unwrap();
+        write!(header, ", nonce={}", quote(&self.nonce)).unwrap();
+        if self.qop_auth {
+            write!(header, ", nc={}, cnonce={}, qop=auth", nc, quote(cnonce)).unwrap();
+        }
+        write!(header, ", response=\"{}\"", response).unwrap();
+        if let Some(ref opaque) = self.opaque {
+            write!(header, ", opaque={}", quote(opaque)).unwrap();
+        }
+        if self.userhash {
+            header += ", userhash=true";
+        }
+        header
+    }
+}
+
+/// Splits a `WWW-Authenticate` header value into its challenges: the
+/// auth scheme, and the parameters, with quoted values unescaped.
+fn parse_challenges(header_value: &str) -> Vec<(String, Vec<(String, String)>)> {
+    let mut challenges: Vec<(String, Vec<(String, String)>)> = Vec::new();
+    for part in split_unquoted_commas(header_value) {
+        let mut part = part.trim();
+        // A challenge starts with the scheme, followed by its first
+        // parameter (or token68) after a space.
+        let first_word = part.split([' ', '\t']).next().unwrap_or("");
+        if !first_word.is_empty() && !first_word.contains('=') {
+            challenges.push((first_word.to_string(), Vec::new()));
+            part = part[first_word.len()..].trim();
+        }
+        if let (Some((key, value)), Some((_, params))) =
+            (part.split_once('='), challenges.last_mut())
+        {
+            params.push((key.trim().to_string(), unquote(value.trim())));
+        }
+    }
+    challenges
+}
+
+/// Splits `value` at the commas that aren't in a quoted string.
+fn split_unquoted_commas(value: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut quoted, mut escaped) = (false, false);
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                parts.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+fn unquote(value: &str) -> String {
+    let inner = match value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    {
+        Some(inner) => inner,
+        None => return value.to_string(),
+    };
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.extend(chars.next()),
+            c => unquoted.push(c),
+        }
+    }
+    unquoted
+}
+
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Returns a random client nonce, without depending on a random number
+/// generator crate.
+fn cnonce() -> String {
+    let mut nonce = String::with_capacity(32);
+    for _ in 0..2 {
+        let mut hasher = RandomState::new().build_hasher();
+        if let Ok(elapsed) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+            hasher.write_u128(elapsed.as_nanos());
+        }
+        write!(nonce, "{:016x}", hasher.finish()).unwrap();
+    }
+    nonce
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        write!(hex, "{:02x}", byte).unwrap();
+    }
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_challenges, Algorithm, Challenge, DigestAuth};
+
+    #[test]
+    fn hashes() {
+        assert_eq!(Algorithm::Md5.hash(""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(
+            Algorithm::Md5Sess.hash("The quick brown fox jumps over the lazy dog"),
+            "9e107d9d372bb6826bd81d3542a419d6"
+        );
+        assert_eq!(
+            Algorithm::Sha256.hash(""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            Algorithm::Sha256Sess.hash("abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn challenge_parsing() {
+        let challenges = parse_challenges(
+            "Basic realm=\"simple\", Digest realm=\"a, \\\"quoted\\\" realm\",\
+             qop=\"auth, auth-int\", nonce=abc, Bearer",
+        );
+        assert_eq!(challenges.len(), 3);
+        assert_eq!(challenges[0].0, "Basic");
+        assert_eq!(challenges[1].0, "Digest");
+        assert_eq!(
+            challenges[1].1,
+            [
+                ("realm".to_string(), "a, \"quoted\" realm".to_string()),
+                ("qop".to_string(), "auth, auth-int".to_string()),
+                ("nonce".to_string(), "abc".to_string()),
+            ]
+        );
+        assert!(challenges[2].1.is_empty());
+
+        // Unsupported algorithms and qops are skipped.
+        let header = "Digest realm=a, nonce=1, algorithm=SHA-512-256, \
+                      Digest realm=b, nonce=2, qop=auth-int, \
+                      Digest realm=c, nonce=3, algorithm=sha-256-sess";
+        let challenge = Challenge::select([header].iter().copied()).unwrap();
+        assert_eq!(challenge.realm, "c");
+        assert_eq!(challenge.nonce, "3");
+    }
+
+    /// The examples from RFC 7616 section 3.9.1.
+    #[test]
+    fn rfc_examples() {
+        let header = |algorithm: &str| {
+            format!(
+                "Digest realm=\"http-auth@example.org\", qop=\"auth, auth-int\", \
+                 algorithm={}, nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", \
+                 opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\"",
+                algorithm
+            )
+        };
+        let authorization = |algorithm: &str| {
+            let header = header(algorithm);
+            let challenge = Challenge::select([header.as_str()].iter().copied()).unwrap();
+            challenge.authorization_with_cnonce(
+                "Mufasa",
+                "Circle of Life",
+                "GET",
+                "/dir/index.html",
+                1,
+                "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ",
+            )
+        };
+        assert_eq!(
+            authorization("MD5"),
+            "Digest username=\"Mufasa\", realm=\"http-auth@example.org\", \
+             uri=\"/dir/index.html\", algorithm=MD5, \
+             nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", nc=00000001, \
+             cnonce=\"f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ\", qop=auth, \
+             response=\"8ca523f5e9506fed4657c9700eebdbec\", \
+             opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\""
+        );
+        assert!(authorization("SHA-256").contains(
+            "response=\"753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1\""
+        ));
+    }
+
+    #[test]
+    fn nonce_count() {
+        let auth = DigestAuth::new("user".to_string(), "password".to_string());
+        assert_eq!(auth.preemptive("GET", "/"), None);
+        let challenge = "Digest realm=\"r\", nonce=\"n\", qop=auth";
+        let first = auth
+            .respond([challenge].iter().copied(), "GET", "/")
+            .unwrap();
+        assert!(first.contains("nc=00000001"));
+        let second = auth.clone().preemptive("GET", "/other").unwrap();
+        assert!(second.contains("nc=00000002") && second.contains("uri=\"/other\""));
+    }
+}
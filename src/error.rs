@@ -12,6 +12,10 @@ pub enum Error {
     /// The response body contains invalid UTF-8, so the `as_str()`
     /// conversion failed.
     InvalidUtf8InBody(str::Utf8Error),
+    /// Ran into a Serde error while serializing a request body or
+    /// deserializing a response body.
+    #[cfg(feature = "json")]
+    SerdeJsonError(serde_json::Error),
 
     #[cfg(feature = "rustls")]
     /// Ran into a rustls error while creating the connection.
@@ -26,7 +30,8 @@ pub enum Error {
     /// of bytes.
     MalformedChunkEnd,
     /// Couldn't parse the `Content-Length` header's value as an
-    /// `usize`.
+    /// `usize`, or the response has several `Content-Length` values
+    /// that differ.
     MalformedContentLength,
     /// The response contains headers whose total size surpasses
     /// [Request::with_max_headers_size](crate::request::Request::with_max_headers_size).
@@ -70,6 +75,45 @@ pub enum Error {
     ProxyConnect,
     /// The provided credentials were rejected by the proxy server.
     InvalidProxyCreds,
+    /// The proxy server answered the `CONNECT` request with a status
+    /// other than 2xx (or 401 and 407, see
+    /// [`InvalidProxyCreds`](enum.Error.html#variant.InvalidProxyCreds)),
+    /// so no tunnel was opened. Contains the status code and reason
+    /// phrase of the proxy's response, or for SOCKS proxies, the reply
+    /// code and its meaning.
+    ProxyRefused(i32, String),
+    /// The request's `Host`, `Content-Length` and `Transfer-Encoding`
+    /// headers contradict each other or the body, so the request was
+    /// not sent. The string describes the conflict.
+    ConflictingHeaders(&'static str),
+    /// A request header's name is not a valid token, or its value
+    /// contains control characters such as line breaks, so the request
+    /// was not sent. The string is the name of the header.
+    InvalidHeader(String),
+    /// The request's [`Method::Custom`](crate::Method::Custom) is not a
+    /// valid token, so the request was not sent.
+    InvalidMethod(String),
+    /// The path or query of the request's URL (or of a redirect's
+    /// `Location`) contains spaces or control characters such as line
+    /// breaks, so the request was not sent. The string is the path and
+    /// query.
+    InvalidRequestTarget(String),
+    /// The request's body was set with
+    /// [`Request::with_body_reader`](struct.Request.html#method.with_body_reader),
+    /// and the reader was already read by an earlier send of the
+    /// request (or one of its clones).
+    BodyAlreadyRead,
+    /// Got redirected from an `https://` URL to an `http://` one, which
+    /// the request's [`RedirectPolicy`](crate::RedirectPolicy) doesn't
+    /// allow.
+    InsecureRedirect,
+    /// One of the request's timeouts was reached. The
+    /// [`TimeoutKind`](enum.TimeoutKind.html) tells which one.
+    Timeout(TimeoutKind),
+    /// A saved [`CookieJar`](struct.CookieJar.html) could not be
+    /// loaded, because it's malformed. The string describes the
+    /// problem.
+    InvalidCookieJar(String),
     // TODO: Uncomment these two for 3.0
     // /// The URL does not start with http:// or https://.
     // InvalidProtocol,
@@ -91,12 +135,14 @@ impl fmt::Display for Error {
             #[cfg(feature = "std")]
             IoError(err) => write!(f, "{}", err),
             InvalidUtf8InBody(err) => write!(f, "{}", err),
+            #[cfg(feature = "json")]
+            SerdeJsonError(err) => write!(f, "{}", err),
 
             #[cfg(feature = "rustls")]
             RustlsCreateConnection(err) => write!(f, "error creating rustls connection: {}", err),
             MalformedChunkLength => write!(f, "non-usize chunk length with transfer-encoding: chunked"),
             MalformedChunkEnd => write!(f, "chunk did not end after reading the expected amount of bytes"),
-            MalformedContentLength => write!(f, "non-usize content length"),
+            MalformedContentLength => write!(f, "non-usize or conflicting content length"),
             HeadersOverflow => write!(f, "the headers' total size surpassed max_headers_size"),
             StatusLineOverflow => write!(f, "the status line length surpassed max_status_line_length"),
             AddressNotFound => write!(f, "could not resolve host to a socket address"),
@@ -111,6 +157,15 @@ impl fmt::Display for Error {
             BadProxyCreds => write!(f, "the provided proxy credentials are malformed"),
             ProxyConnect => write!(f, "could not connect to the proxy server"),
             InvalidProxyCreds => write!(f, "the provided proxy credentials are invalid"),
+            ProxyRefused(status_code, reason_phrase) => write!(f, "the proxy refused to open a tunnel: {} {}", status_code, reason_phrase),
+            ConflictingHeaders(conflict) => write!(f, "conflicting request headers: {}", conflict),
+            InvalidHeader(name) => write!(f, "the request header {:?} has an invalid name or value", name),
+            InvalidMethod(method) => write!(f, "the request method {:?} is not a valid token", method),
+            InvalidRequestTarget(target) => write!(f, "the request target {:?} contains spaces or control characters", target),
+            BodyAlreadyRead => write!(f, "the request body reader has already been read"),
+            InsecureRedirect => write!(f, "got redirected from https to http, which the redirect policy does not allow"),
+            Timeout(kind) => write!(f, "{}", kind),
+            InvalidCookieJar(problem) => write!(f, "the cookie jar is malformed: {}", problem),
             // TODO: Uncomment these two for 3.0
             // InvalidProtocol => write!(f, "the url does not start with http:// or https://"),
             // InvalidProtocolInRedirect => write!(f, "got redirected to an absolute url which does not start with http:// or https://"),
@@ -127,6 +182,8 @@ impl error::Error for Error {
             #[cfg(feature = "std")]
             IoError(err) => Some(err),
             InvalidUtf8InBody(err) => Some(err),
+            #[cfg(feature = "json")]
+            SerdeJsonError(err) => Some(err),
             #[cfg(feature = "rustls")]
             RustlsCreateConnection(err) => Some(err),
             _ => None,
@@ -137,6 +194,53 @@ impl error::Error for Error {
 #[cfg(feature = "std")]
 impl From<io::Error> for Error {
     fn from(other: io::Error) -> Error {
-        Error::IoError(other)
+        // Timeouts reached while reading or writing are passed through
+        // io::Errors, with the TimeoutKind as the inner error.
+        match other
+            .get_ref()
+            .and_then(|err| err.downcast_ref::<TimeoutKind>())
+        {
+            Some(kind) => Error::Timeout(*kind),
+            None => Error::IoError(other),
+        }
     }
 }
+
+/// The timeouts that can be reached while sending a request, see
+/// [`Error::Timeout`](enum.Error.html#variant.Timeout).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimeoutKind {
+    /// Connecting to the server took longer than the
+    /// [connect timeout](struct.Request.html#method.with_connect_timeout).
+    Connect,
+    /// The TLS handshake took longer than the
+    /// [TLS handshake timeout](struct.Request.html#method.with_tls_handshake_timeout).
+    TlsHandshake,
+    /// Sending a part of the request took longer than the
+    /// [write timeout](struct.Request.html#method.with_write_timeout).
+    Write,
+    /// Nothing was received from the server for longer than the
+    /// [read timeout](struct.Request.html#method.with_read_timeout).
+    Read,
+    /// The whole request, including redirects and reading the
+    /// response, took longer than the
+    /// [total timeout](struct.Request.html#method.with_total_timeout).
+    Total,
+}
+
+impl fmt::Display for TimeoutKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimeoutKind::Connect => write!(f, "the connect timeout of the request was reached"),
+            TimeoutKind::TlsHandshake => {
+                write!(f, "the TLS handshake timeout of the request was reached")
+            }
+            TimeoutKind::Write => write!(f, "the write timeout of the request was reached"),
+            TimeoutKind::Read => write!(f, "the read timeout of the request was reached"),
+            TimeoutKind::Total => write!(f, "the timeout of the request was reached"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for TimeoutKind {}
@@ -0,0 +1,250 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::{self, Vec};
+use core::iter::FromIterator;
+use core::slice;
+
+/// A list of HTTP header fields.
+///
+/// Header names are looked up case-insensitively, but are otherwise
+/// kept as they were given or received. Unlike a regular map, a name
+/// can appear more than once (eg. multiple `Set-Cookie` headers), and
+/// the fields are kept in the order they were added in, which is also
+/// the order they are sent or were received in.
+///
+/// # Example
+///
+/// ```
+/// let mut headers = minireq::HeaderMap::new();
+/// headers.append("Set-Cookie", "a=1");
+/// headers.append("set-cookie", "b=2");
+/// headers.insert("Content-Type", "text/plain");
+///
+/// assert_eq!(headers.get("SET-COOKIE"), Some("a=1"));
+/// assert_eq!(headers.get_all("set-cookie").collect::<Vec<_>>(), ["a=1", "b=2"]);
+/// assert_eq!(headers.get("content-type"), Some("text/plain"));
+/// ```
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct HeaderMap {
+    entries: Vec<(String, String)>,
+}
+
+impl HeaderMap {
+    /// Creates an empty `HeaderMap`.
+    pub fn new() -> HeaderMap {
+        HeaderMap {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Returns the value of the first field called `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        let i = self.position(name)?;
+        Some(&self.entries[i].1)
+    }
+
+    /// Returns the values of all the fields called `name`, in order.
+    pub fn get_all<'a, 'b>(&'a self, name: &'b str) -> impl Iterator<Item = &'a str> + 'b
+    where
+        'a: 'b,
+    {
+        self.entries
+            .iter()
+            .filter(move |(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns true if there is at least one field called `name`.
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Sets the field called `name` to `value`, replacing all the
+    /// existing fields with that name. The field keeps the position of
+    /// the first replaced field.
+    ///
+    /// Returns the value of the first replaced field, if there was one.
+    pub fn insert<K: Into<String>, V: Into<String>>(
+        &mut self,
+        name: K,
+        value: V,
+    ) -> Option<String> {
+        let name = name.into();
+        let value = value.into();
+        match self.position(&name) {
+            Some(i) => {
+                let mut index = 0;
+                self.entries.retain(|(k, _)| {
+                    let keep = index <= i || !k.eq_ignore_ascii_case(&name);
+                    index += 1;
+                    keep
+                });
+                let (_, old_value) = core::mem::replace(&mut self.entries[i], (name, value));
+                Some(old_value)
+            }
+            None => {
+                self.entries.push((name, value));
+                None
+            }
+        }
+    }
+
+    /// Adds a field called `name` after the existing fields, without
+    /// replacing any fields with the same name.
+    pub fn append<K: Into<String>, V: Into<String>>(&mut self, name: K, value: V) {
+        self.entries.push((name.into(), value.into()));
+    }
+
+    /// Removes all the fields called `name`, and returns the value of
+    /// the first one.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let i = self.position(name)?;
+        let (_, value) = self.entries.remove(i);
+        self.entries.retain(|(k, _)| !k.eq_ignore_ascii_case(name));
+        Some(value)
+    }
+
+    /// Returns the amount of fields, counting each repeated field
+    /// separately.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if there are no fields.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns an iterator over the names and values of the fields, in
+    /// order.
+    pub fn iter(&self) -> HeaderMapIter<'_> {
+        HeaderMapIter(self.entries.iter())
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|(k, _)| k.eq_ignore_ascii_case(name))
+    }
+}
+
+/// An iterator over the fields of a [`HeaderMap`], created by
+/// [`HeaderMap::iter`].
+pub struct HeaderMapIter<'a>(slice::Iter<'a, (String, String)>);
+
+impl<'a> Iterator for HeaderMapIter<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a> IntoIterator for &'a HeaderMap {
+    type Item = (&'a str, &'a str);
+    type IntoIter = HeaderMapIter<'a>;
+
+    fn into_iter(self) -> HeaderMapIter<'a> {
+        self.iter()
+    }
+}
+
+impl IntoIterator for HeaderMap {
+    type Item = (String, String);
+    type IntoIter = vec::IntoIter<(String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> Extend<(K, V)> for HeaderMap {
+    /// Appends the fields, see [`HeaderMap::append`].
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        for (k, v) in iter {
+            self.append(k, v);
+        }
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for HeaderMap {
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.extend(iter);
+        headers
+    }
+}
+
+impl From<BTreeMap<String, String>> for HeaderMap {
+    fn from(map: BTreeMap<String, String>) -> HeaderMap {
+        map.into_iter().collect()
+    }
+}
+
+impl From<HeaderMap> for BTreeMap<String, String> {
+    /// Converts the fields into a map with lowercase names. The values
+    /// of repeated fields are joined with `", "`, as described in [RFC
+    /// 7230 section 3.2.2](https://datatracker.ietf.org/doc/html/rfc7230#section-3.2.2).
+    fn from(headers: HeaderMap) -> BTreeMap<String, String> {
+        let mut map = BTreeMap::<String, String>::new();
+        for (mut k, v) in headers {
+            k.make_ascii_lowercase();
+            match map.get_mut(&k) {
+                Some(existing) => {
+                    existing.push_str(", ");
+                    existing.push_str(&v);
+                }
+                None => {
+                    map.insert(k, v);
+                }
+            }
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HeaderMap;
+    use alloc::collections::BTreeMap;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn insert_replaces_all_case_insensitively() {
+        let mut headers = HeaderMap::new();
+        headers.append("Accept", "a");
+        headers.append("X-Foo", "1");
+        headers.append("accept", "b");
+        assert_eq!(headers.insert("ACCEPT", "c"), Some("a".into()));
+        assert_eq!(
+            headers.iter().collect::<Vec<_>>(),
+            [("ACCEPT", "c"), ("X-Foo", "1")]
+        );
+    }
+
+    #[test]
+    fn remove_removes_all() {
+        let mut headers = HeaderMap::new();
+        headers.append("via", "a");
+        headers.append("Via", "b");
+        assert_eq!(headers.remove("VIA"), Some("a".into()));
+        assert!(headers.is_empty());
+        assert_eq!(headers.remove("via"), None);
+    }
+
+    #[test]
+    fn legacy_map_conversion() {
+        let mut headers = HeaderMap::new();
+        headers.append("Link", "<a>");
+        headers.append("link", "<b>");
+        headers.append("Host", "example.com");
+        let map = BTreeMap::from(headers);
+        assert_eq!(map.get("link").map(|s| s.as_str()), Some("<a>, <b>"));
+        assert_eq!(map.get("host").map(|s| s.as_str()), Some("example.com"));
+        assert_eq!(HeaderMap::from(map).len(), 2);
+    }
+}
@@ -21,16 +21,19 @@ impl Port {
 
 /// URL split into its parts. See [RFC 3986 section
 /// 3](https://datatracker.ietf.org/doc/html/rfc3986#section-3). Note that the
-/// userinfo component is not allowed since [RFC
-/// 7230](https://datatracker.ietf.org/doc/html/rfc7230#section-2.7.1).
+/// userinfo component is not allowed in requests since [RFC
+/// 7230](https://datatracker.ietf.org/doc/html/rfc7230#section-2.7.1), so
+/// it's kept apart to be sent as an `Authorization` header instead.
 ///
 /// ```text
-/// scheme "://" host [ ":" port ] path [ "?" query ] [ "#" fragment ]
+/// scheme "://" [ userinfo "@" ] host [ ":" port ] path [ "?" query ] [ "#" fragment ]
 /// ```
 #[derive(Clone, PartialEq)]
 pub(crate) struct HttpUrl {
     /// If scheme is "https", true, if "http", false.
     pub(crate) https: bool,
+    /// `[userinfo "@"]` without the `@`, still percent-encoded.
+    pub(crate) userinfo: Option<String>,
     /// `host`
     pub(crate) host: String,
     /// `[":" port]`
@@ -66,6 +69,13 @@ impl HttpUrl {
             return Err(Error::Other("invalid protocol in url"));
         };
 
+        // The userinfo ends at the last "@" before the path.
+        let authority_length = url.find(['/', '?', '#']).unwrap_or(url.len());
+        let (userinfo, url) = match url[..authority_length].rfind('@') {
+            Some(at) => (Some(url[..at].to_string()), &url[at + 1..]),
+            None => (None, url),
+        };
+
         let mut host = String::new();
         let mut port = String::new();
         let mut resource = String::new(); // At first this is the path and query, after # this becomes fragment.
@@ -169,6 +179,7 @@ impl HttpUrl {
 
         Ok(HttpUrl {
             https,
+            userinfo,
             host,
             port,
             path_and_query,
@@ -190,6 +201,14 @@ impl HttpUrl {
         Ok(())
     }
 
+    /// Returns true if `other` has the same scheme, host and port as
+    /// this URL.
+    pub(crate) fn same_origin(&self, other: &HttpUrl) -> bool {
+        self.https == other.https
+            && self.host.eq_ignore_ascii_case(&other.host)
+            && self.port.port() == other.port.port()
+    }
+
     /// Writes the `path [ "?" query ] [ "#" fragment ]` part to the destination.
     pub(crate) fn write_resource_to<W: Write>(&self, dst: &mut W) -> fmt::Result {
         write!(
@@ -202,6 +221,13 @@ impl HttpUrl {
     }
 }
 
+impl fmt::Display for HttpUrl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_base_url_to(f)?;
+        self.write_resource_to(f)
+    }
+}
+
 // https://github.com/kornelski/rust_urlencoding/blob/a4df8027ab34a86a63f1be727965cf101556403f/src/enc.rs#L130-L136
 // Converts a UTF-8 byte to a single hexadecimal character
 #[cfg(feature = "urlencoding")]
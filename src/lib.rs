@@ -70,13 +70,38 @@
 //!
 //! ## `proxy`
 //!
-//! This feature enables HTTP proxy support. See [Proxy].
+//! This feature enables HTTP, HTTPS and SOCKS proxy support. See [Proxy].
 //!
 //! ## `urlencoding`
 //!
 //! This feature enables percent-encoding for the URL resource when
 //! creating a request and any subsequently added parameters from
-//! [`Request::with_param`].
+//! [`Request::with_param`]. It also enables sending HTML forms with
+//! `Request::with_form`, and decoding them with `Response::form`.
+//!
+//! ## `gzip`, `deflate`, `brotli` and `zstd`
+//!
+//! These features enable transparent decompression of response bodies
+//! with the corresponding `Content-Encoding`, and add the codings to an
+//! automatically sent `Accept-Encoding` header. Decompression can be
+//! turned off per request with `Request::with_decompression(false)`.
+//!
+//! ## `digest-auth`
+//!
+//! This feature adds `Request::with_digest_auth`,
+//! `Client::with_digest_auth` and `Proxy::with_digest_auth`, and
+//! answers the Digest challenges of servers and proxies (see [RFC
+//! 7616](https://datatracker.ietf.org/doc/html/rfc7616)). The MD5 and
+//! SHA-256 hashes it needs come from the
+//! [`md-5`](https://crates.io/crates/md-5) and
+//! [`sha2`](https://crates.io/crates/sha2) crates.
+//!
+//! ## `json`
+//!
+//! This feature adds `Request::with_json` for sending
+//! [serde](https://serde.rs) serializable values as JSON, and
+//! `Response::json` and `ResponseLazy::json` for deserializing JSON
+//! responses. Errors are returned as `Error::SerdeJsonError`.
 //!
 //! # Examples
 //!
@@ -119,6 +144,27 @@
 //! # fn main() -> Result<(), Box<dyn std::error::Error>> { Ok(()) }
 //! ```
 //!
+//! Large bodies can be streamed from anything that implements
+//! `std::io::Read` with `with_body_reader`, instead of being loaded
+//! into memory first. They are sent with `Transfer-Encoding: chunked`,
+//! unless a `Content-Length` header is set.
+//!
+//! ```no_run
+//! # #[cfg(feature = "std")]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let file = std::fs::File::open("upload.bin")?;
+//! let response = minireq::put("http://example.com/upload")
+//!     .with_body_reader(file)
+//!     .send()?;
+//! # Ok(()) }
+//! # #[cfg(not(feature = "std"))]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> { Ok(()) }
+//! ```
+//!
+//! File uploads and other `multipart/form-data` forms can be built
+//! with [`Multipart`](struct.Multipart.html) and sent with
+//! `with_multipart`.
+//!
 //! ## Headers (sending)
 //!
 //! To add a header, add `with_header("Key", "Value")` before
@@ -139,10 +185,11 @@
 //!
 //! Reading the headers sent by the servers is done via the
 //! [`headers`](struct.Response.html#structfield.headers) field of the
-//! [`Response`](struct.Response.html). Note: the header field names
-//! (that is, the *keys* of the `HashMap`) are all lowercase: this is
-//! because the names are case-insensitive according to the spec, and
-//! this unifies the casings for easier `get()`ing.
+//! [`Response`](struct.Response.html), which is a [`HeaderMap`]. The
+//! header field names are looked up case-insensitively, and are all
+//! lowercase in the response. Repeated headers, such as `Set-Cookie`,
+//! are all kept, and can be read with
+//! [`get_all()`](struct.HeaderMap.html#method.get_all).
 //!
 //! ```
 //! # #[cfg(feature = "std")]
@@ -172,14 +219,49 @@
 //! # fn main() -> Result<(), Box<dyn std::error::Error>> { Ok(()) }
 //! ```
 //!
+//! Separate limits can be set for connecting, the TLS handshake,
+//! writing the request and waiting for data from the server, and the
+//! error tells which one was reached:
+//!
+//! ```no_run
+//! # #[cfg(feature = "std")]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use minireq::{Error, TimeoutKind};
+//! use std::time::Duration;
+//!
+//! let result = minireq::get("http://example.com/large-file")
+//!     .with_connect_timeout(Duration::from_millis(500))
+//!     // Fails if the server doesn't send anything for 5 seconds...
+//!     .with_read_timeout(Duration::from_secs(5))
+//!     // ...or if the whole download takes over 10 minutes.
+//!     .with_total_timeout(Duration::from_secs(600))
+//!     .send();
+//! match result {
+//!     Err(Error::Timeout(TimeoutKind::Read)) => println!("the server stalled"),
+//!     Err(Error::Timeout(kind)) => println!("timed out: {}", kind),
+//!     other => println!("{:?}", other?.status_code),
+//! }
+//! # Ok(()) }
+//! # #[cfg(not(feature = "std"))]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> { Ok(()) }
+//! ```
+//!
 //! ## Proxy
 //!
 //! To use a proxy server, simply create a `Proxy` instance and use
 //! `.with_proxy()` on your request.
 //!
 //! Supported proxy formats are `host:port` and
-//! `user:password@proxy:host`. Only HTTP CONNECT proxies are
-//! supported at this time.
+//! `user:password@proxy:host`, optionally prefixed with `http://`,
+//! `https://`, `socks4a://`, `socks5://` or `socks5h://`. HTTP and HTTPS
+//! CONNECT, SOCKS4a and SOCKS5 proxies are supported at this time, see
+//! [`Proxy::new`]. Plain-http requests are forwarded to HTTP proxies
+//! as-is, and https requests are tunneled with `CONNECT`, unless
+//! [`Proxy::with_mode`] says otherwise.
+//!
+//! Requests without a proxy use the one in the `http_proxy`,
+//! `https_proxy` or `all_proxy` environment variables, unless the host is
+//! listed in `no_proxy`. See [`Request::with_env_proxy`] to turn this off.
 //!
 //! ```no_run
 //! # #[cfg(feature = "std")]
@@ -197,6 +279,119 @@
 //! # fn main() -> Result<(), Box<dyn std::error::Error>> { Ok(()) }
 //! ```
 //!
+//! ## Redirects
+//!
+//! Redirects are followed according to a [`RedirectPolicy`](struct.RedirectPolicy.html),
+//! which by default follows 301, 302, 303, 307 and 308 redirects,
+//! removes credentials when redirected to another origin, and refuses
+//! to be redirected from https to http.
+//!
+//! ```no_run
+//! # #[cfg(feature = "std")]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let response = minireq::get("http://example.com")
+//!     .with_redirect_policy(minireq::RedirectPolicy::none())
+//!     .send()?;
+//! if let Some(location) = response.headers.get("location") {
+//!     println!("Redirected to {}", location);
+//! }
+//! # Ok(()) }
+//! # #[cfg(not(feature = "std"))]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> { Ok(()) }
+//! ```
+//!
+//! ## Retries
+//!
+//! Requests aren't retried by default. With a
+//! [`RetryPolicy`](struct.RetryPolicy.html), requests with idempotent
+//! methods are retried with exponential backoff after connection errors
+//! and 429, 502, 503 and 504 responses, waiting for as long as
+//! `Retry-After` asks for if it's present.
+//!
+//! ```no_run
+//! # #[cfg(feature = "std")]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let response = minireq::get("http://example.com")
+//!     .with_retry_policy(minireq::RetryPolicy::new().with_max_attempts(5))
+//!     .send()?;
+//! println!("Took {} attempts", response.attempts());
+//! # Ok(()) }
+//! # #[cfg(not(feature = "std"))]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> { Ok(()) }
+//! ```
+//!
+//! ## Clients
+//!
+//! To share a base URL, headers, timeouts and other settings between
+//! many requests, create a [`Client`] and make the requests through
+//! it. Requests made through a client also reuse connections.
+//!
+//! ```no_run
+//! # #[cfg(feature = "std")]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = minireq::Client::new()
+//!     .with_base_url("http://example.com")
+//!     .with_header("Accept", "text/html");
+//! let response = client.get("/index.html").send()?;
+//! # Ok(()) }
+//! # #[cfg(not(feature = "std"))]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> { Ok(()) }
+//! ```
+//!
+//! ## Connection pooling
+//!
+//! To reuse connections between requests to the same host, create a
+//! [`ConnectionPool`] and pass it to each request with
+//! `.with_pool()`. The connection is returned to the pool once the
+//! response body has been read.
+//!
+//! ```no_run
+//! # #[cfg(feature = "std")]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let pool = minireq::ConnectionPool::new();
+//! let first = minireq::get("http://example.com").with_pool(pool.clone()).send()?;
+//! let second = minireq::get("http://example.com").with_pool(pool.clone()).send()?;
+//! # Ok(()) }
+//! # #[cfg(not(feature = "std"))]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> { Ok(()) }
+//! ```
+//!
+//! ## Cookies
+//!
+//! Cookies aren't stored unless the request has a [`CookieJar`]. With
+//! one, the cookies set by the responses (including redirects) are
+//! sent with later requests that use the same jar.
+//!
+//! ```no_run
+//! # #[cfg(feature = "std")]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let jar = minireq::CookieJar::new();
+//! let client = minireq::Client::new().with_cookie_jar(jar.clone());
+//! client.post("http://example.com/login").send()?;
+//! let profile = client.get("http://example.com/profile").send()?;
+//! jar.save_netscape(std::fs::File::create("cookies.txt")?)?;
+//! # Ok(()) }
+//! # #[cfg(not(feature = "std"))]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> { Ok(()) }
+//! ```
+//!
+//! ## Caching
+//!
+//! To avoid downloading resources that haven't changed, give the
+//! requests a [`Cache`]. Fresh responses are then returned from it, and
+//! stale ones are revalidated with the server.
+//!
+//! ```no_run
+//! # #[cfg(feature = "std")]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let cache = minireq::Cache::new(minireq::DiskStore::new("http-cache")?);
+//! let client = minireq::Client::new().with_cache(cache);
+//! let feed = client.get("http://example.com/feed.xml").send()?;
+//! # Ok(()) }
+//! # #[cfg(not(feature = "std"))]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> { Ok(()) }
+//! ```
+//!
 //! # Timeouts
 //!
 //! By default, a request has no timeout. You can change this in two
@@ -218,8 +413,9 @@
 //!   ```
 //!   std::env::set_var("MINREQ_TIMEOUT", "8");
 //!   ```
-//! If the timeout is set with `with_timeout`, the environment
-//! variable will be ignored.
+//! If the timeout is set with `with_timeout` (or
+//! [`with_total_timeout`](struct.Request.html#method.with_total_timeout)),
+//! the environment variable will be ignored.
 
 #![deny(missing_docs)]
 // std::io::Error::other was added in 1.74, so occurrences of this lint can't be
@@ -228,20 +424,58 @@
 
 extern crate alloc;
 
+mod auth;
+mod body;
+#[cfg(feature = "std")]
+mod cache;
+#[cfg(feature = "std")]
+mod client;
 #[cfg(feature = "std")]
 mod connection;
+#[cfg(feature = "std")]
+mod cookie;
+#[cfg(feature = "std")]
+mod date;
+#[cfg(feature = "std")]
+mod decode;
+#[cfg(feature = "digest-auth")]
+mod digest;
 mod error;
+mod headers;
 #[cfg(feature = "std")]
 mod http_url;
+#[cfg(feature = "std")]
+mod multipart;
+#[cfg(feature = "std")]
+mod pool;
 #[cfg(feature = "proxy")]
 mod proxy;
+#[cfg(feature = "std")]
+mod redirect;
 mod request;
 mod response;
+#[cfg(feature = "std")]
+mod retry;
 
+#[cfg(feature = "std")]
+pub use cache::{Cache, CacheEntry, CacheStore, DiskStore, MemoryStore};
+#[cfg(feature = "std")]
+pub use client::Client;
+#[cfg(feature = "std")]
+pub use cookie::{Cookie, CookieJar, SameSite};
 pub use error::*;
+pub use headers::{HeaderMap, HeaderMapIter};
+#[cfg(feature = "std")]
+pub use multipart::{Multipart, Part};
+#[cfg(feature = "std")]
+pub use pool::ConnectionPool;
 #[cfg(feature = "proxy")]
 pub use proxy::*;
+#[cfg(feature = "std")]
+pub use redirect::{RedirectAttempt, RedirectHop, RedirectPolicy};
 pub use request::*;
 pub use response::Response;
 #[cfg(feature = "std")]
 pub use response::ResponseLazy;
+#[cfg(feature = "std")]
+pub use retry::{RetryOutcome, RetryPolicy};
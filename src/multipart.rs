@@ -0,0 +1,377 @@
+use crate::Error;
+use alloc::collections::VecDeque;
+use core::fmt;
+use std::collections::hash_map::RandomState;
+use std::fs::File;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// A `multipart/form-data` request body, as described in [RFC
+/// 7578](https://datatracker.ietf.org/doc/html/rfc7578).
+///
+/// The body is built from [`Part`]s, and is sent with
+/// [`Request::with_multipart`](struct.Request.html#method.with_multipart).
+/// Parts backed by files or readers are streamed while sending,
+/// instead of being loaded into memory.
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> Result<(), minireq::Error> {
+/// use minireq::{Multipart, Part};
+///
+/// let form = Multipart::new()
+///     .with_text("description", "A picture of a cat")
+///     .with_part(Part::file("picture", "cat.png")?.with_content_type("image/png"))
+///     .with_part(Part::bytes("notes", "purrs a lot").with_filename("notes.txt"));
+/// let response = minireq::post("http://example.com/upload")
+///     .with_multipart(form)
+///     .send()?;
+/// # Ok(()) }
+/// ```
+pub struct Multipart {
+    boundary: String,
+    parts: Vec<Part>,
+}
+
+impl Multipart {
+    /// Creates an empty form with a random boundary.
+    pub fn new() -> Multipart {
+        Multipart {
+            boundary: random_boundary(),
+            parts: Vec::new(),
+        }
+    }
+
+    /// Replaces the random boundary with `boundary`.
+    ///
+    /// The boundary must not appear in any of the parts, which is why
+    /// a random one is used by default.
+    pub fn with_boundary<T: Into<String>>(mut self, boundary: T) -> Multipart {
+        self.boundary = boundary.into();
+        self
+    }
+
+    /// Returns the boundary that separates the parts.
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// Adds a text field called `name`.
+    pub fn with_text<T: Into<String>, U: Into<String>>(self, name: T, value: U) -> Multipart {
+        self.with_part(Part::text(name, value))
+    }
+
+    /// Adds a part.
+    pub fn with_part(mut self, part: Part) -> Multipart {
+        self.parts.push(part);
+        self
+    }
+
+    /// Returns the value of the `Content-Type` header for this form.
+    pub(crate) fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    /// Returns the length of the encoded form, if the lengths of all
+    /// the parts are known.
+    pub(crate) fn content_length(&self) -> Option<u64> {
+        let mut length = self.closing_delimiter().len() as u64;
+        for part in &self.parts {
+            length += part.head(&self.boundary).len() as u64 + part.length()? + 2;
+        }
+        Some(length)
+    }
+
+    /// Returns the encoded form if all the parts are in memory, or the
+    /// form back otherwise.
+    pub(crate) fn into_bytes(self) -> Result<Vec<u8>, Multipart> {
+        if !self
+            .parts
+            .iter()
+            .all(|part| matches!(part.data, PartData::Bytes(_)))
+        {
+            return Err(self);
+        }
+        let mut bytes = Vec::new();
+        for part in self.parts.iter() {
+            bytes.extend(part.head(&self.boundary).as_bytes());
+            if let PartData::Bytes(ref data) = part.data {
+                bytes.extend(data);
+            }
+            bytes.extend(b"\r\n");
+        }
+        bytes.extend(self.closing_delimiter().as_bytes());
+        Ok(bytes)
+    }
+
+    /// Returns a reader over the encoded form, which reads the parts
+    /// one after another.
+    pub(crate) fn into_reader(self) -> MultipartReader {
+        let mut segments: VecDeque<Box<dyn Read + Send>> = VecDeque::new();
+        let closing_delimiter = self.closing_delimiter();
+        for part in self.parts {
+            segments.push_back(Box::new(Cursor::new(part.head(&self.boundary))));
+            segments.push_back(match part.data {
+                PartData::Bytes(data) => Box::new(Cursor::new(data)),
+                PartData::Reader(reader, Some(length)) => Box::new(reader.take(length)),
+                PartData::Reader(reader, None) => reader,
+            });
+            segments.push_back(Box::new(&b"\r\n"[..]));
+        }
+        segments.push_back(Box::new(Cursor::new(closing_delimiter)));
+        MultipartReader { segments }
+    }
+
+    fn closing_delimiter(&self) -> String {
+        format!("--{}--\r\n", self.boundary)
+    }
+}
+
+impl Default for Multipart {
+    fn default() -> Multipart {
+        Multipart::new()
+    }
+}
+
+impl fmt::Debug for Multipart {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Multipart")
+            .field("boundary", &self.boundary)
+            .field("parts", &self.parts)
+            .finish()
+    }
+}
+
+/// A single field of a [`Multipart`] form.
+pub struct Part {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<String>,
+    data: PartData,
+}
+
+enum PartData {
+    Bytes(Vec<u8>),
+    Reader(Box<dyn Read + Send>, Option<u64>),
+}
+
+impl Part {
+    /// Creates a text field called `name`.
+    pub fn text<T: Into<String>, U: Into<String>>(name: T, value: U) -> Part {
+        Part::new(name.into(), PartData::Bytes(value.into().into_bytes()))
+    }
+
+    /// Creates a field called `name` containing `bytes`, with the
+    /// content type `application/octet-stream`.
+    pub fn bytes<T: Into<String>, U: Into<Vec<u8>>>(name: T, bytes: U) -> Part {
+        Part::new(name.into(), PartData::Bytes(bytes.into()))
+            .with_content_type("application/octet-stream")
+    }
+
+    /// Creates a field called `name` containing the file at `path`,
+    /// with the content type `application/octet-stream` and the
+    /// file's name as the filename.
+    ///
+    /// The file is opened right away, but only read while the request
+    /// is being sent.
+    pub fn file<T: Into<String>, P: AsRef<Path>>(name: T, path: P) -> Result<Part, Error> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        let mut part = Part::new(name.into(), PartData::Reader(Box::new(file), Some(length)))
+            .with_content_type("application/octet-stream");
+        if let Some(filename) = path.file_name() {
+            part = part.with_filename(filename.to_string_lossy());
+        }
+        Ok(part)
+    }
+
+    /// Creates a field called `name` containing everything read from
+    /// `reader`, with the content type `application/octet-stream`.
+    ///
+    /// Since the length of the part isn't known, the form is sent with
+    /// `Transfer-Encoding: chunked`. Use
+    /// [`reader_with_length`](#method.reader_with_length) if the
+    /// length is known.
+    pub fn reader<T: Into<String>, R: Read + Send + 'static>(name: T, reader: R) -> Part {
+        Part::new(name.into(), PartData::Reader(Box::new(reader), None))
+            .with_content_type("application/octet-stream")
+    }
+
+    /// Creates a field called `name` containing the first `length`
+    /// bytes read from `reader`, with the content type
+    /// `application/octet-stream`.
+    ///
+    /// If all of a form's parts have a known length, it's sent with a
+    /// `Content-Length`. Sending fails if the reader ends before
+    /// `length` bytes were read.
+    pub fn reader_with_length<T: Into<String>, R: Read + Send + 'static>(
+        name: T,
+        reader: R,
+        length: u64,
+    ) -> Part {
+        Part::new(
+            name.into(),
+            PartData::Reader(Box::new(reader), Some(length)),
+        )
+        .with_content_type("application/octet-stream")
+    }
+
+    /// Sets the filename sent with the part.
+    pub fn with_filename<T: Into<String>>(mut self, filename: T) -> Part {
+        self.filename = Some(filename.into());
+        self
+    }
+
+    /// Sets the content type of the part.
+    pub fn with_content_type<T: Into<String>>(mut self, content_type: T) -> Part {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    fn new(name: String, data: PartData) -> Part {
+        Part {
+            name,
+            filename: None,
+            content_type: None,
+            data,
+        }
+    }
+
+    fn length(&self) -> Option<u64> {
+        match self.data {
+            PartData::Bytes(ref data) => Some(data.len() as u64),
+            PartData::Reader(_, length) => length,
+        }
+    }
+
+    /// Returns the delimiter and the headers that come before the
+    /// part's contents.
+    fn head(&self, boundary: &str) -> String {
+        let mut head = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"",
+            boundary,
+            escape_quoted(&self.name)
+        );
+        if let Some(ref filename) = self.filename {
+            head += &format!("; filename=\"{}\"", escape_quoted(filename));
+        }
+        head += "\r\n";
+        if let Some(ref content_type) = self.content_type {
+            head += &format!("Content-Type: {}\r\n", content_type);
+        }
+        head += "\r\n";
+        head
+    }
+}
+
+impl fmt::Debug for Part {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Part")
+            .field("name", &self.name)
+            .field("filename", &self.filename)
+            .field("content_type", &self.content_type)
+            .field("length", &self.length())
+            .finish()
+    }
+}
+
+/// Reads the segments of an encoded form one after another.
+pub(crate) struct MultipartReader {
+    segments: VecDeque<Box<dyn Read + Send>>,
+}
+
+impl Read for MultipartReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while let Some(segment) = self.segments.front_mut() {
+            let read = segment.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            self.segments.pop_front();
+        }
+        Ok(0)
+    }
+}
+
+/// Escapes a name for a quoted Content-Disposition parameter, the way
+/// browsers do, see the [HTML
+/// standard](https://html.spec.whatwg.org/multipage/form-control-infrastructure.html#multipart-form-data).
+fn escape_quoted(s: &str) -> String {
+    s.replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Generates a boundary that's very unlikely to appear in the parts,
+/// without depending on a random number generator crate.
+fn random_boundary() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(elapsed) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(elapsed.as_nanos());
+    }
+    let a = hasher.finish();
+    hasher.write_u64(a);
+    let b = hasher.finish();
+    format!("minireq-{:016x}{:016x}", a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Multipart, Part};
+    use std::io::Read;
+
+    #[test]
+    fn encodes_parts() {
+        let form = Multipart::new()
+            .with_boundary("XYZ")
+            .with_text("a", "1")
+            .with_part(
+                Part::bytes("b\"", "22")
+                    .with_filename("b.txt")
+                    .with_content_type("text/plain"),
+            );
+        let expected = "--XYZ\r\n\
+            Content-Disposition: form-data; name=\"a\"\r\n\r\n\
+            1\r\n\
+            --XYZ\r\n\
+            Content-Disposition: form-data; name=\"b%22\"; filename=\"b.txt\"\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            22\r\n\
+            --XYZ--\r\n";
+        assert_eq!(form.content_length(), Some(expected.len() as u64));
+        assert_eq!(form.into_bytes().unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn streams_reader_parts() {
+        let form = Multipart::new()
+            .with_boundary("XYZ")
+            .with_part(Part::reader_with_length("a", &b"abcdef"[..], 3))
+            .with_part(Part::reader("b", &b"xyz"[..]).with_filename("b"));
+        assert_eq!(form.content_length(), None);
+        let form = form.into_bytes().unwrap_err();
+        let mut body = String::new();
+        form.into_reader().read_to_string(&mut body).unwrap();
+        assert_eq!(
+            body,
+            "--XYZ\r\n\
+            Content-Disposition: form-data; name=\"a\"\r\n\
+            Content-Type: application/octet-stream\r\n\r\n\
+            abc\r\n\
+            --XYZ\r\n\
+            Content-Disposition: form-data; name=\"b\"; filename=\"b\"\r\n\
+            Content-Type: application/octet-stream\r\n\r\n\
+            xyz\r\n\
+            --XYZ--\r\n"
+        );
+    }
+
+    #[test]
+    fn random_boundaries_differ() {
+        assert_ne!(Multipart::new().boundary(), Multipart::new().boundary());
+    }
+}
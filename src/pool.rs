@@ -0,0 +1,206 @@
+use crate::connection::HttpStream;
+#[cfg(feature = "rustls")]
+use crate::connection::TlsConfig;
+#[cfg(feature = "proxy")]
+use crate::proxy::Proxy;
+use crate::request::ParsedRequest;
+use alloc::sync::Arc;
+use core::fmt;
+use core::time::Duration;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const DEFAULT_MAX_IDLE_PER_HOST: usize = 8;
+
+/// A pool of idle keep-alive connections, shared between requests.
+///
+/// By default every request opens a new connection (and for HTTPS, a
+/// new TLS session) and closes it once the response has been read.
+/// Requests that are given a pool with
+/// [`Request::with_pool`](struct.Request.html#method.with_pool)
+/// instead look for an idle connection to the same scheme, host, port
+/// and proxy in the pool before connecting, and return their
+/// connection to the pool once the response body has been read to the
+/// end.
+///
+/// A connection is only returned to the pool if the end of the body
+/// could be determined from the response (ie. it had a
+/// `Content-Length` or was chunked), and neither the request nor the
+/// response contained `Connection: close`. Connections that have been
+/// idle for longer than the idle timeout are closed instead of being
+/// reused.
+///
+/// The pool is cheap to clone, and all clones share the same idle
+/// connections.
+///
+/// # Example
+///
+/// ```no_run
+/// # #[cfg(feature = "std")]
+/// # fn main() -> Result<(), minireq::Error> {
+/// let pool = minireq::ConnectionPool::new();
+/// for _ in 0..10 {
+///     // All of these requests are sent over the same connection.
+///     let response = minireq::get("http://example.com")
+///         .with_pool(pool.clone())
+///         .send()?;
+///     println!("{}", response.status_code);
+/// }
+/// # Ok(()) }
+/// # #[cfg(not(feature = "std"))]
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> { Ok(()) }
+/// ```
+#[derive(Clone)]
+pub struct ConnectionPool {
+    inner: Arc<Mutex<PoolInner>>,
+}
+
+struct PoolInner {
+    idle: HashMap<PoolKey, Vec<IdleStream>>,
+    idle_timeout: Duration,
+    max_idle_per_host: usize,
+}
+
+struct IdleStream {
+    stream: HttpStream,
+    idle_since: Instant,
+}
+
+/// The properties of a request that decide whether two requests can
+/// share a connection.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct PoolKey {
+    https: bool,
+    host: String,
+    port: u32,
+    #[cfg(feature = "proxy")]
+    proxy: Option<Proxy>,
+    #[cfg(feature = "rustls")]
+    tls_config: Option<TlsConfig>,
+}
+
+impl PoolKey {
+    pub(crate) fn new(request: &ParsedRequest) -> PoolKey {
+        PoolKey {
+            https: request.url.https,
+            host: request.url.host.clone(),
+            port: request.url.port.port(),
+            #[cfg(feature = "proxy")]
+            proxy: request.config.proxy.clone(),
+            #[cfg(feature = "rustls")]
+            tls_config: request.config.tls_config.clone(),
+        }
+    }
+}
+
+impl ConnectionPool {
+    /// Creates a new, empty connection pool.
+    ///
+    /// Idle connections are closed after 90 seconds, and at most 8
+    /// idle connections are kept per host.
+    pub fn new() -> ConnectionPool {
+        ConnectionPool {
+            inner: Arc::new(Mutex::new(PoolInner {
+                idle: HashMap::new(),
+                idle_timeout: DEFAULT_IDLE_TIMEOUT,
+                max_idle_per_host: DEFAULT_MAX_IDLE_PER_HOST,
+            })),
+        }
+    }
+
+    /// Sets how long a connection can sit unused in the pool before
+    /// it is closed. 90 seconds by default.
+    pub fn with_idle_timeout(self, idle_timeout: Duration) -> ConnectionPool {
+        self.lock().idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Sets how many idle connections are kept for each
+    /// scheme/host/port/proxy/TLS configuration combination. 8 by default.
+    pub fn with_max_idle_per_host(self, max_idle_per_host: usize) -> ConnectionPool {
+        self.lock().max_idle_per_host = max_idle_per_host;
+        self
+    }
+
+    /// Returns the amount of idle connections currently in the pool.
+    pub fn idle_connections(&self) -> usize {
+        let mut inner = self.lock();
+        inner.evict_expired();
+        inner.idle.values().map(Vec::len).sum()
+    }
+
+    /// Closes all the idle connections in the pool.
+    pub fn clear(&self) {
+        self.lock().idle.clear();
+    }
+
+    /// Takes an idle connection matching `key` out of the pool, if
+    /// there is one that is still open.
+    pub(crate) fn acquire(&self, key: &PoolKey) -> Option<HttpStream> {
+        let mut inner = self.lock();
+        inner.evict_expired();
+        let streams = inner.idle.get_mut(key)?;
+        while let Some(idle) = streams.pop() {
+            if idle.stream.is_reusable() {
+                #[cfg(feature = "log")]
+                log::trace!("Reusing pooled connection to {}.", key.host);
+                return Some(idle.stream);
+            }
+        }
+        None
+    }
+
+    /// Puts a connection whose response has been fully read back into
+    /// the pool.
+    pub(crate) fn release(&self, key: PoolKey, stream: HttpStream) {
+        let mut inner = self.lock();
+        let max_idle_per_host = inner.max_idle_per_host;
+        let streams = inner.idle.entry(key).or_default();
+        if streams.len() < max_idle_per_host {
+            streams.push(IdleStream {
+                stream,
+                idle_since: Instant::now(),
+            });
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, PoolInner> {
+        // The pool only holds idle connections, so a panic while it
+        // was locked can't have left it in a state that matters.
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl PoolInner {
+    fn evict_expired(&mut self) {
+        let idle_timeout = self.idle_timeout;
+        for streams in self.idle.values_mut() {
+            streams.retain(|idle| idle.idle_since.elapsed() < idle_timeout);
+        }
+        self.idle.retain(|_, streams| !streams.is_empty());
+    }
+}
+
+impl Default for ConnectionPool {
+    fn default() -> ConnectionPool {
+        ConnectionPool::new()
+    }
+}
+
+impl PartialEq for ConnectionPool {
+    fn eq(&self, other: &ConnectionPool) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl Eq for ConnectionPool {}
+
+impl fmt::Debug for ConnectionPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConnectionPool")
+            .field("idle_connections", &self.idle_connections())
+            .finish()
+    }
+}
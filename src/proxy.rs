@@ -1,26 +1,62 @@
+use crate::auth::basic_auth;
+#[cfg(feature = "digest-auth")]
+use crate::digest::Challenge;
 use crate::error::Error;
-use crate::ParsedRequest;
-use base64::engine::general_purpose::STANDARD;
-use base64::engine::Engine;
+use crate::response::ConnectResponse;
+use crate::{HeaderMap, ParsedRequest};
+
+#[cfg(feature = "std")]
+pub(crate) mod env;
+pub(crate) mod socks;
 
 /// Kind of proxy connection (Basic, Digest, etc)
-#[derive(Clone, PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub(crate) enum ProxyKind {
     Basic,
+    #[cfg(feature = "digest-auth")]
+    Digest,
+    Socks4a,
+    /// SOCKS5, with host names resolved locally.
+    Socks5,
+    /// SOCKS5, with host names resolved by the proxy.
+    Socks5h,
+}
+
+/// How requests are sent through an HTTP or HTTPS proxy, see
+/// [`Proxy::with_mode`](struct.Proxy.html#method.with_mode).
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ProxyMode {
+    /// Tunnel https requests with CONNECT, and forward http requests. The
+    /// default.
+    Auto,
+    /// Tunnel all requests with CONNECT, eg. for proxies that only
+    /// support tunneling.
+    Tunnel,
+    /// Forward all requests, including https ones, which the proxy then
+    /// sends over its own TLS connection to the server. The proxy can
+    /// read and modify these requests and their responses.
+    Forward,
 }
 
-/// Proxy configuration. Only HTTP CONNECT proxies are supported (no SOCKS or
-/// HTTPS).
+/// Proxy configuration. HTTP and HTTPS CONNECT proxies, and SOCKS4a and
+/// SOCKS5 proxies are supported. Plain-http requests are forwarded to HTTP
+/// and HTTPS proxies, see [`ProxyMode`](enum.ProxyMode.html).
 ///
-/// When credentials are provided, the Basic authentication type is used for
-/// Proxy-Authorization.
-#[derive(Clone, PartialEq, Eq, Debug)]
+/// When credentials are provided to an HTTP proxy, the Basic authentication
+/// type is used for Proxy-Authorization. With the `digest-auth` feature, if
+/// the proxy answers with a 407 and a Digest challenge, the CONNECT (or
+/// forwarded) request is sent once more with the Digest credentials, and
+/// `with_digest_auth` keeps the Basic credentials from being sent at all.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub struct Proxy {
     pub(crate) server: String,
     pub(crate) port: u32,
     pub(crate) user: Option<String>,
     pub(crate) password: Option<String>,
     pub(crate) kind: ProxyKind,
+    /// Whether the connection to the proxy itself is secured with TLS.
+    pub(crate) https: bool,
+    pub(crate) mode: ProxyMode,
 }
 
 impl Proxy {
@@ -46,27 +82,54 @@ impl Proxy {
     /// Supported proxy format is:
     ///
     /// ```plaintext
-    /// [http://][user[:password]@]host[:port]
+    /// [protocol://][user[:password]@]host[:port]
     /// ```
     ///
-    /// The default port is 8080, to be changed to 1080 in minireq 3.0.
+    /// Where the protocol is one of:
+    ///
+    /// - `http` (the default): an HTTP proxy, which is asked to open a
+    ///   tunnel with a CONNECT request.
+    /// - `https`: an HTTP proxy that is connected to with TLS, so that the
+    ///   CONNECT request and its credentials are only sent encrypted. This
+    ///   requires one of the `https` features. Requests to https URLs
+    ///   through it are secured with TLS inside that TLS session.
+    /// - `socks4a`: a SOCKS4a proxy, which resolves host names itself. The
+    ///   user is sent as the user ID, and the password is ignored.
+    /// - `socks5`: a SOCKS5 proxy, with host names resolved locally before
+    ///   connecting through the proxy.
+    /// - `socks5h`: a SOCKS5 proxy, which resolves host names itself, eg.
+    ///   for reaching hosts that only the proxy can resolve, or for Tor.
+    ///
+    /// SOCKS5 proxies are sent the credentials with the username/password
+    /// authentication method if they ask for it.
+    ///
+    /// The default port is 1080 for SOCKS proxies, 443 for HTTPS proxies,
+    /// and 8080 for HTTP proxies, to be changed to 1080 in minireq 3.0.
     ///
     /// # Example
     ///
     /// ```
     /// let proxy = minireq::Proxy::new("user:password@localhost:1080").unwrap();
     /// let request = minireq::post("http://example.com").with_proxy(proxy);
+    ///
+    /// let tor = minireq::Proxy::new("socks5h://127.0.0.1:9050").unwrap();
+    /// let request = minireq::get("http://example.onion").with_proxy(tor);
     /// ```
     ///
     pub fn new<S: AsRef<str>>(proxy: S) -> Result<Self, Error> {
         let proxy = proxy.as_ref();
-        let authority = if let Some((proto, auth)) = split_once(proxy, "://") {
-            if proto != "http" {
-                return Err(Error::BadProxy);
-            }
-            auth
+        let (kind, https, authority) = if let Some((proto, auth)) = split_once(proxy, "://") {
+            let (kind, https) = match proto {
+                "http" => (ProxyKind::Basic, false),
+                "https" => (ProxyKind::Basic, true),
+                "socks4a" => (ProxyKind::Socks4a, false),
+                "socks5" => (ProxyKind::Socks5, false),
+                "socks5h" => (ProxyKind::Socks5h, false),
+                _ => return Err(Error::BadProxy),
+            };
+            (kind, https, auth)
         } else {
-            proxy
+            (ProxyKind::Basic, false, proxy)
         };
 
         let ((user, password), host) = if let Some((userinfo, host)) = rsplit_once(authority, "@") {
@@ -76,30 +139,109 @@ impl Proxy {
         };
 
         let (host, port) = Proxy::parse_address(host)?;
+        let default_port = match kind {
+            _ if https => 443,
+            ProxyKind::Basic => 8080,
+            #[cfg(feature = "digest-auth")]
+            ProxyKind::Digest => 8080,
+            ProxyKind::Socks4a | ProxyKind::Socks5 | ProxyKind::Socks5h => 1080,
+        };
 
         Ok(Self {
             server: host,
             user,
             password,
-            port: port.unwrap_or(8080),
-            kind: ProxyKind::Basic,
+            port: port.unwrap_or(default_port),
+            kind,
+            https,
+            mode: ProxyMode::Auto,
         })
     }
 
-    pub(crate) fn connect(&self, proxied_req: &ParsedRequest) -> String {
-        let authorization = if let Some(user) = &self.user {
-            match self.kind {
-                ProxyKind::Basic => {
-                    let creds = if let Some(password) = &self.password {
-                        STANDARD.encode(format!("{}:{}", user, password))
-                    } else {
-                        STANDARD.encode(user)
-                    };
-                    format!("Proxy-Authorization: Basic {}\r\n", creds)
-                }
-            }
-        } else {
-            String::new()
+    /// Returns true if this is a SOCKS proxy, rather than an HTTP one.
+    pub(crate) fn is_socks(&self) -> bool {
+        matches!(
+            self.kind,
+            ProxyKind::Socks4a | ProxyKind::Socks5 | ProxyKind::Socks5h
+        )
+    }
+
+    /// Makes the credentials only be sent in answer to a Digest challenge
+    /// from the proxy, instead of being sent up front with the Basic
+    /// authentication type, which exposes the password to anyone who
+    /// can see the traffic to the proxy. SOCKS proxies are not affected.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let proxy = minireq::Proxy::new("user:password@localhost:1080")
+    ///     .unwrap()
+    ///     .with_digest_auth();
+    /// ```
+    #[cfg(feature = "digest-auth")]
+    pub fn with_digest_auth(mut self) -> Proxy {
+        if self.kind == ProxyKind::Basic {
+            self.kind = ProxyKind::Digest;
+        }
+        self
+    }
+
+    /// Sets how requests are sent through this proxy, if it's an HTTP or
+    /// HTTPS proxy. By default, https requests are tunneled with a CONNECT
+    /// request, so that their TLS session is with the server, and http
+    /// requests are forwarded: sent to the proxy with the full URL as the
+    /// request target (`GET http://example.com/ HTTP/1.1`), and the
+    /// credentials in each request's `Proxy-Authorization` header.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use minireq::{Proxy, ProxyMode};
+    ///
+    /// let proxy = Proxy::new("localhost:3128").unwrap().with_mode(ProxyMode::Tunnel);
+    /// ```
+    pub fn with_mode(mut self, mode: ProxyMode) -> Proxy {
+        self.mode = mode;
+        self
+    }
+
+    /// Returns true if requests to http (or https, if `https` is true)
+    /// URLs are forwarded to this proxy, rather than tunneled.
+    pub(crate) fn forwards(&self, https: bool) -> bool {
+        if self.is_socks() {
+            return false;
+        }
+        match self.mode {
+            ProxyMode::Auto => !https,
+            ProxyMode::Tunnel => false,
+            ProxyMode::Forward => true,
+        }
+    }
+
+    /// Returns the `Proxy-Authorization` value for the Basic credentials,
+    /// if there are credentials and they may be sent up front.
+    pub(crate) fn basic_authorization(&self) -> Option<String> {
+        let user = self.user.as_ref()?;
+        if self.kind != ProxyKind::Basic {
+            return None;
+        }
+        Some(basic_auth(user, self.password.as_deref()))
+    }
+
+    /// Returns the CONNECT request for `proxied_req`, with the given
+    /// `Proxy-Authorization` value, or the Basic credentials if there
+    /// isn't one.
+    pub(crate) fn connect(
+        &self,
+        proxied_req: &ParsedRequest,
+        authorization: Option<&str>,
+    ) -> String {
+        let authorization = match authorization
+            .map(str::to_string)
+            .or_else(|| self.basic_authorization())
+        {
+            Some(authorization) => format!("Proxy-Authorization: {}\r\n", authorization),
+            None => String::new(),
         };
         let host = &proxied_req.url.host;
         let port = proxied_req.url.port.port();
@@ -109,15 +251,43 @@ impl Proxy {
         )
     }
 
-    pub(crate) fn verify_response(response: &[u8]) -> Result<(), Error> {
-        let response_string = String::from_utf8_lossy(response);
-        let top_line = response_string.lines().next().ok_or(Error::ProxyConnect)?;
-        let status_code = top_line.split_whitespace().nth(1).ok_or(Error::BadProxy)?;
+    /// Returns the `Proxy-Authorization` value answering the Digest
+    /// challenge of a 407 response, if it has one and there are
+    /// credentials to answer it with. `method` and `uri` are the ones
+    /// of the request that was answered. Always None without the
+    /// `digest-auth` feature.
+    #[cfg_attr(not(feature = "digest-auth"), allow(unused_variables))]
+    pub(crate) fn digest_authorization(
+        &self,
+        status_code: i32,
+        headers: &HeaderMap,
+        method: &str,
+        uri: &str,
+    ) -> Option<String> {
+        #[cfg(feature = "digest-auth")]
+        {
+            let user = self.user.as_ref()?;
+            if status_code != 407 {
+                return None;
+            }
+            let challenge = Challenge::select(headers.get_all("proxy-authenticate"))?;
+            let password = self.password.as_deref().unwrap_or("");
+            Some(challenge.authorization(user, password, method, uri, 1))
+        }
+        #[cfg(not(feature = "digest-auth"))]
+        None
+    }
 
-        match status_code {
-            "200" => Ok(()),
-            "401" | "407" => Err(Error::InvalidProxyCreds),
-            _ => Err(Error::BadProxy),
+    /// Checks that the proxy opened the tunnel, see [RFC 9110 section
+    /// 9.3.6](https://datatracker.ietf.org/doc/html/rfc9110#section-9.3.6).
+    pub(crate) fn verify_response(response: &ConnectResponse) -> Result<(), Error> {
+        match response.status_code {
+            200..=299 => Ok(()),
+            401 | 407 => Err(Error::InvalidProxyCreds),
+            status_code => Err(Error::ProxyRefused(
+                status_code,
+                response.reason_phrase.clone(),
+            )),
         }
     }
 }
@@ -153,6 +323,22 @@ mod tests {
         assert_eq!(proxy.port, 9999);
     }
 
+    #[test]
+    fn basic_authorization() {
+        let proxy = Proxy::new("Aladdin:sesame@localhost").unwrap();
+        assert_eq!(
+            proxy.basic_authorization().as_deref(),
+            Some("Basic QWxhZGRpbjpzZXNhbWU=")
+        );
+        let proxy = Proxy::new("user@localhost").unwrap();
+        assert_eq!(
+            proxy.basic_authorization().as_deref(),
+            Some("Basic dXNlcjo=")
+        );
+        let proxy = Proxy::new("localhost").unwrap();
+        assert_eq!(proxy.basic_authorization(), None);
+    }
+
     #[test]
     fn parse_regular_proxy_with_protocol() {
         let proxy = Proxy::new("http://localhost:1080").unwrap();
@@ -161,4 +347,31 @@ mod tests {
         assert_eq!(proxy.server, String::from("localhost"));
         assert_eq!(proxy.port, 1080);
     }
+
+    #[test]
+    fn parse_socks_proxy() {
+        use super::ProxyKind;
+
+        let proxy = Proxy::new("socks5h://user:pw@localhost").unwrap();
+        assert_eq!(proxy.kind, ProxyKind::Socks5h);
+        assert_eq!(proxy.user, Some(String::from("user")));
+        assert_eq!(proxy.port, 1080);
+        let proxy = Proxy::new("socks4a://localhost:9050").unwrap();
+        assert_eq!(proxy.kind, ProxyKind::Socks4a);
+        assert_eq!(proxy.port, 9050);
+        assert_eq!(
+            Proxy::new("socks5://localhost").unwrap().kind,
+            ProxyKind::Socks5
+        );
+        assert!(Proxy::new("socks4://localhost").is_err());
+    }
+
+    #[test]
+    fn parse_https_proxy() {
+        let proxy = Proxy::new("https://user:pw@proxy.example.com").unwrap();
+        assert!(proxy.https);
+        assert_eq!(proxy.server, String::from("proxy.example.com"));
+        assert_eq!(proxy.port, 443);
+        assert!(!Proxy::new("http://localhost").unwrap().https);
+    }
 }
@@ -0,0 +1,184 @@
+//! Picking the proxy for a request from the environment, following
+//! [curl's rules](https://everything.curl.dev/usingcurl/proxies/env).
+
+use super::Proxy;
+use crate::http_url::HttpUrl;
+use std::net::IpAddr;
+
+/// Returns the proxy that the `http_proxy`, `https_proxy` and
+/// `all_proxy` environment variables set for `url`, unless `url` is
+/// excluded by `no_proxy`.
+pub(crate) fn proxy_for(url: &HttpUrl) -> Option<Proxy> {
+    resolve(url, |name| std::env::var(name).ok())
+}
+
+fn resolve<F: Fn(&str) -> Option<String>>(url: &HttpUrl, var: F) -> Option<Proxy> {
+    let var = |name: &str| var(name).filter(|value| !value.trim().is_empty());
+
+    if let Some((_name, no_proxy)) = lookup(&var, &["no_proxy", "NO_PROXY"]) {
+        if let Some(_entry) = no_proxy_match(&no_proxy, &url.host) {
+            #[cfg(feature = "log")]
+            log::debug!(
+                "Not using a proxy for {}, {} has {}.",
+                url.host,
+                _name,
+                _entry
+            );
+            return None;
+        }
+    }
+
+    // `HTTP_PROXY` is only read in lower case, as CGI scripts set
+    // `HTTP_PROXY` from the request's `Proxy` header.
+    let names: &[&str] = if url.https {
+        &["https_proxy", "HTTPS_PROXY", "all_proxy", "ALL_PROXY"]
+    } else {
+        &["http_proxy", "all_proxy", "ALL_PROXY"]
+    };
+    let (_name, value) = match lookup(&var, names) {
+        Some(found) => found,
+        None => {
+            #[cfg(feature = "log")]
+            log::trace!("No proxy set in the environment for {}.", url.host);
+            return None;
+        }
+    };
+    let proxy = Proxy::new(value.trim());
+    #[cfg(feature = "log")]
+    match proxy {
+        Ok(_) => log::debug!("Using the proxy in {} for {}.", _name, url.host),
+        Err(ref err) => log::warn!("Ignoring {}, it is not a valid proxy: {}", _name, err),
+    }
+    proxy.ok()
+}
+
+/// Returns the first of the variables `names` that is set, and its value.
+fn lookup<'a, F: Fn(&str) -> Option<String>>(
+    var: &F,
+    names: &[&'a str],
+) -> Option<(&'a str, String)> {
+    names
+        .iter()
+        .find_map(|&name| var(name).map(|value| (name, value)))
+}
+
+/// Returns the entry of `no_proxy` that `host` matches, if any. The
+/// entries are separated by commas or whitespace, and are either `*`,
+/// a domain that matches itself and its subdomains, an IP address, or
+/// a CIDR block.
+fn no_proxy_match<'a>(no_proxy: &'a str, host: &str) -> Option<&'a str> {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let host = host.strip_suffix('.').unwrap_or(host);
+    let ip = host.parse::<IpAddr>().ok();
+    no_proxy
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|entry| !entry.is_empty())
+        .find(|entry| {
+            if *entry == "*" {
+                return true;
+            }
+            if let Some(ip) = ip {
+                if ip_matches(ip, entry) {
+                    return true;
+                }
+            }
+            let domain = entry.strip_prefix('.').unwrap_or(entry);
+            let domain = domain.strip_suffix('.').unwrap_or(domain);
+            host.eq_ignore_ascii_case(domain)
+                || (host.len() > domain.len()
+                    && host.as_bytes()[host.len() - domain.len() - 1] == b'.'
+                    && host[host.len() - domain.len()..].eq_ignore_ascii_case(domain))
+        })
+}
+
+/// Returns true if `ip` is the address `entry`, or is in the CIDR block
+/// `entry`.
+fn ip_matches(ip: IpAddr, entry: &str) -> bool {
+    let (network, prefix_len) = match entry.split_once('/') {
+        Some((network, prefix_len)) => (network, Some(prefix_len)),
+        None => (entry, None),
+    };
+    let network = network.trim_start_matches('[').trim_end_matches(']');
+    let (ip, network, bits) = match (ip, network.parse::<IpAddr>()) {
+        (IpAddr::V4(ip), Ok(IpAddr::V4(network))) => {
+            (u32::from(ip) as u128, u32::from(network) as u128, 32)
+        }
+        (IpAddr::V6(ip), Ok(IpAddr::V6(network))) => (u128::from(ip), u128::from(network), 128),
+        _ => return false,
+    };
+    let prefix_len = match prefix_len.map(str::parse::<u32>) {
+        None => bits,
+        Some(Ok(prefix_len)) if prefix_len <= bits => prefix_len,
+        Some(_) => return false,
+    };
+    // Shifting a u128 by 128 would overflow.
+    prefix_len == 0 || ip >> (bits - prefix_len) == network >> (bits - prefix_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{no_proxy_match, resolve};
+    use crate::http_url::HttpUrl;
+    use crate::Proxy;
+
+    fn resolve_with(url: &str, vars: &[(&str, &str)]) -> Option<Proxy> {
+        let url = HttpUrl::parse(url, None).unwrap();
+        resolve(&url, |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    #[test]
+    fn precedence() {
+        let proxy = |server: &str| Some(Proxy::new(server).unwrap());
+        let vars = [
+            ("HTTP_PROXY", "a:1"),
+            ("HTTPS_PROXY", "b:2"),
+            ("ALL_PROXY", "c:3"),
+        ];
+        assert_eq!(resolve_with("http://example.com", &vars), proxy("c:3"));
+        assert_eq!(resolve_with("https://example.com", &vars), proxy("b:2"));
+
+        let vars = [
+            ("https_proxy", ""),
+            ("http_proxy", "a:1"),
+            ("all_proxy", "c:3"),
+        ];
+        assert_eq!(resolve_with("http://example.com", &vars), proxy("a:1"));
+        assert_eq!(resolve_with("https://example.com", &vars), proxy("c:3"));
+        assert_eq!(resolve_with("https://example.com", &[]), None);
+        assert_eq!(
+            resolve_with("http://example.com", &[("http_proxy", "a:b")]),
+            None
+        );
+
+        let vars = [("all_proxy", "c:3"), ("no_proxy", "example.com")];
+        assert_eq!(resolve_with("http://www.example.com", &vars), None);
+        assert_eq!(resolve_with("http://example.org", &vars), proxy("c:3"));
+    }
+
+    #[test]
+    fn no_proxy_entries() {
+        let no_proxy = "localhost, .example.com  10.0.0.0/8,192.168.1.1,[::1]";
+        assert_eq!(no_proxy_match(no_proxy, "localhost"), Some("localhost"));
+        assert_eq!(
+            no_proxy_match(no_proxy, "example.com"),
+            Some(".example.com")
+        );
+        assert_eq!(
+            no_proxy_match(no_proxy, "A.Example.com."),
+            Some(".example.com")
+        );
+        assert_eq!(no_proxy_match(no_proxy, "notexample.com"), None);
+        assert_eq!(no_proxy_match(no_proxy, "10.20.30.40"), Some("10.0.0.0/8"));
+        assert_eq!(no_proxy_match(no_proxy, "11.0.0.1"), None);
+        assert_eq!(no_proxy_match(no_proxy, "192.168.1.1"), Some("192.168.1.1"));
+        assert_eq!(no_proxy_match(no_proxy, "192.168.1.2"), None);
+        assert_eq!(no_proxy_match(no_proxy, "[::1]"), Some("[::1]"));
+        assert_eq!(no_proxy_match("*", "example.com"), Some("*"));
+        assert_eq!(no_proxy_match("fd00::/8", "[fd12::1]"), Some("fd00::/8"));
+        assert_eq!(no_proxy_match("0.0.0.0/0", "1.2.3.4"), Some("0.0.0.0/0"));
+    }
+}
@@ -0,0 +1,294 @@
+//! The SOCKS4a ([SOCKS4](https://www.openssh.com/txt/socks4.protocol),
+//! [SOCKS4a](https://www.openssh.com/txt/socks4a.protocol)) and SOCKS5
+//! ([RFC 1928](https://datatracker.ietf.org/doc/html/rfc1928), [RFC
+//! 1929](https://datatracker.ietf.org/doc/html/rfc1929)) handshakes.
+
+use super::{Proxy, ProxyKind};
+use crate::error::Error;
+use std::io::{Read, Write};
+use std::net::IpAddr;
+
+/// Where the proxy should open the tunnel to.
+pub(crate) enum Destination<'a> {
+    /// A host name, resolved by the proxy.
+    Host(&'a str),
+    /// An address, either given in the URL or resolved by us.
+    Ip(IpAddr),
+}
+
+/// Asks the proxy at the other end of `stream` to open a tunnel to
+/// `destination` and `port`. Once this returns, the stream is
+/// connected to the destination.
+pub(crate) fn handshake<S: Read + Write>(
+    stream: &mut S,
+    proxy: &Proxy,
+    destination: Destination,
+    port: u16,
+) -> Result<(), Error> {
+    let request = match proxy.kind {
+        ProxyKind::Socks4a => socks4a_request(proxy, &destination, port)?,
+        _ => {
+            socks5_authenticate(stream, proxy)?;
+            socks5_request(&destination, port)?
+        }
+    };
+    stream.write_all(&request)?;
+    stream.flush()?;
+    match proxy.kind {
+        ProxyKind::Socks4a => read_socks4_reply(stream),
+        _ => read_socks5_reply(stream),
+    }
+}
+
+fn socks4a_request(proxy: &Proxy, destination: &Destination, port: u16) -> Result<Vec<u8>, Error> {
+    let mut request = vec![4, 1];
+    request.extend_from_slice(&port.to_be_bytes());
+    let host = match *destination {
+        Destination::Ip(IpAddr::V4(ip)) => {
+            request.extend_from_slice(&ip.octets());
+            None
+        }
+        // SOCKS4 has no way to connect to IPv6 addresses.
+        Destination::Ip(IpAddr::V6(_)) => return Err(Error::BadProxy),
+        Destination::Host(host) => {
+            // The invalid address 0.0.0.x tells the proxy that the
+            // host name follows the user ID.
+            request.extend_from_slice(&[0, 0, 0, 1]);
+            Some(host)
+        }
+    };
+    let user = proxy.user.as_deref().unwrap_or("");
+    if user.contains('\0') {
+        return Err(Error::BadProxyCreds);
+    }
+    request.extend_from_slice(user.as_bytes());
+    request.push(0);
+    if let Some(host) = host {
+        request.extend_from_slice(host.as_bytes());
+        request.push(0);
+    }
+    Ok(request)
+}
+
+fn read_socks4_reply<S: Read>(stream: &mut S) -> Result<(), Error> {
+    // The version (0), status, and the port and address, which are
+    // ignored for CONNECT.
+    let mut reply = [0; 8];
+    stream.read_exact(&mut reply)?;
+    if reply[0] != 0 {
+        return Err(Error::ProxyConnect);
+    }
+    let reason = match reply[1] {
+        0x5a => return Ok(()),
+        0x5b => "request rejected or failed",
+        0x5c => "the proxy could not reach the client's identd",
+        0x5d => "the client's identd reported a different user ID",
+        _ => "unknown status",
+    };
+    Err(Error::ProxyRefused(reply[1] as i32, reason.to_string()))
+}
+
+/// Negotiates the authentication method, and sends the username and
+/// password if the proxy asks for them.
+fn socks5_authenticate<S: Read + Write>(stream: &mut S, proxy: &Proxy) -> Result<(), Error> {
+    const NO_AUTH: u8 = 0x00;
+    const USERNAME_PASSWORD: u8 = 0x02;
+    const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+
+    if proxy.user.is_some() {
+        stream.write_all(&[5, 2, NO_AUTH, USERNAME_PASSWORD])?;
+    } else {
+        stream.write_all(&[5, 1, NO_AUTH])?;
+    }
+    stream.flush()?;
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply)?;
+    if reply[0] != 5 {
+        return Err(Error::ProxyConnect);
+    }
+    let user = match (reply[1], &proxy.user) {
+        (NO_AUTH, _) => return Ok(()),
+        (USERNAME_PASSWORD, Some(user)) => user,
+        (NO_ACCEPTABLE_METHODS, _) => return Err(Error::InvalidProxyCreds),
+        _ => return Err(Error::ProxyConnect),
+    };
+
+    let password = proxy.password.as_deref().unwrap_or("");
+    if user.len() > 255 || password.len() > 255 {
+        return Err(Error::BadProxyCreds);
+    }
+    let mut request = vec![1, user.len() as u8];
+    request.extend_from_slice(user.as_bytes());
+    request.push(password.len() as u8);
+    request.extend_from_slice(password.as_bytes());
+    stream.write_all(&request)?;
+    stream.flush()?;
+    stream.read_exact(&mut reply)?;
+    // Any status but 0 means the credentials were rejected.
+    if reply[1] != 0 {
+        return Err(Error::InvalidProxyCreds);
+    }
+    Ok(())
+}
+
+fn socks5_request(destination: &Destination, port: u16) -> Result<Vec<u8>, Error> {
+    // The version, the CONNECT command, and a reserved byte.
+    let mut request = vec![5, 1, 0];
+    match *destination {
+        Destination::Ip(IpAddr::V4(ip)) => {
+            request.push(1);
+            request.extend_from_slice(&ip.octets());
+        }
+        Destination::Ip(IpAddr::V6(ip)) => {
+            request.push(4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Destination::Host(host) => {
+            if host.len() > 255 {
+                return Err(Error::AddressNotFound);
+            }
+            request.push(3);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    Ok(request)
+}
+
+fn read_socks5_reply<S: Read>(stream: &mut S) -> Result<(), Error> {
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[0] != 5 {
+        return Err(Error::ProxyConnect);
+    }
+    let reason = match reply[1] {
+        0 => None,
+        1 => Some("general SOCKS server failure"),
+        2 => Some("connection not allowed by ruleset"),
+        3 => Some("network unreachable"),
+        4 => Some("host unreachable"),
+        5 => Some("connection refused"),
+        6 => Some("TTL expired"),
+        7 => Some("command not supported"),
+        8 => Some("address type not supported"),
+        _ => Some("unknown reply"),
+    };
+    if let Some(reason) = reason {
+        return Err(Error::ProxyRefused(reply[1] as i32, reason.to_string()));
+    }
+
+    // The address the proxy bound for the tunnel isn't needed, but it
+    // has to be read past to get to the tunnel's data.
+    let address_len = match reply[3] {
+        1 => 4,
+        4 => 16,
+        3 => {
+            let mut len = [0];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        _ => return Err(Error::ProxyConnect),
+    };
+    let mut bound_address = vec![0; address_len + 2];
+    stream.read_exact(&mut bound_address)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{handshake, Destination};
+    use crate::{Error, Proxy};
+    use std::io::{self, Read, Write};
+
+    /// A stream that reads from canned replies, and records what's
+    /// written to it.
+    struct MockStream {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl MockStream {
+        fn new(input: &[u8]) -> MockStream {
+            MockStream {
+                input: io::Cursor::new(input.to_vec()),
+                output: Vec::new(),
+            }
+        }
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn socks5_remote_dns() {
+        let proxy = Proxy::new("socks5h://user:pw@localhost").unwrap();
+        let mut stream = MockStream::new(&[
+            5, 2, // username/password
+            1, 0, // authenticated
+            5, 0, 0, 3, 4, b'h', b'o', b's', b't', 0, 80,   // connected
+            b'x', // the tunnel's first byte
+        ]);
+        handshake(&mut stream, &proxy, Destination::Host("example.com"), 443).unwrap();
+        let mut expected = vec![5, 2, 0, 2, 1, 4, b'u', b's', b'e', b'r', 2, b'p', b'w'];
+        expected.extend_from_slice(&[5, 1, 0, 3, 11]);
+        expected.extend_from_slice(b"example.com");
+        expected.extend_from_slice(&[1, 187]);
+        assert_eq!(stream.output, expected);
+        assert_eq!(stream.input.position(), 15);
+    }
+
+    #[test]
+    fn socks5_errors() {
+        let proxy = Proxy::new("socks5://localhost").unwrap();
+        let destination = || Destination::Ip([127, 0, 0, 1].into());
+
+        let mut stream = MockStream::new(&[5, 0, 5, 5, 0, 1, 0, 0, 0, 0, 0, 0]);
+        match handshake(&mut stream, &proxy, destination(), 80) {
+            Err(Error::ProxyRefused(5, reason)) => assert_eq!(reason, "connection refused"),
+            other => panic!("expected ProxyRefused, got {:?}", other),
+        }
+        assert_eq!(stream.output, [5, 1, 0, 5, 1, 0, 1, 127, 0, 0, 1, 0, 80]);
+
+        let mut stream = MockStream::new(&[5, 0xff]);
+        let result = handshake(&mut stream, &proxy, destination(), 80);
+        assert!(matches!(result, Err(Error::InvalidProxyCreds)));
+    }
+
+    #[test]
+    fn socks4a() {
+        let proxy = Proxy::new("socks4a://user@localhost").unwrap();
+        let mut stream = MockStream::new(&[0, 0x5a, 0, 0, 0, 0, 0, 0]);
+        handshake(&mut stream, &proxy, Destination::Host("a.b"), 80).unwrap();
+        assert_eq!(
+            stream.output,
+            [4, 1, 0, 80, 0, 0, 0, 1, b'u', b's', b'e', b'r', 0, b'a', b'.', b'b', 0]
+        );
+
+        let mut stream = MockStream::new(&[0, 0x5b, 0, 0, 0, 0, 0, 0]);
+        let result = handshake(
+            &mut stream,
+            &proxy,
+            Destination::Ip([10, 0, 0, 1].into()),
+            80,
+        );
+        assert!(matches!(result, Err(Error::ProxyRefused(0x5b, _))));
+        assert_eq!(
+            stream.output,
+            [4, 1, 0, 80, 10, 0, 0, 1, b'u', b's', b'e', b'r', 0]
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use crate::{HeaderMap, Method};
+use alloc::sync::Arc;
+use core::fmt;
+
+/// Decides which redirects a [`Request`](struct.Request.html) follows,
+/// and how.
+///
+/// With the default policy, which is used unless
+/// [`Request::with_redirect_policy`](struct.Request.html#method.with_redirect_policy)
+/// is called:
+///
+/// - 301, 302, 303, 307 and 308 redirects are followed, up to the
+///   request's
+///   [`max_redirects`](struct.Request.html#method.with_max_redirects).
+/// - 303 redirects of any request but `HEAD` are followed with a
+///   `GET` request, without the body. 307 and 308 redirects, and by
+///   default 301 and 302 redirects, keep the method and the body.
+/// - The `Authorization`, `Cookie` and `Host` headers are removed when
+///   redirected to a different origin (scheme, host or port), so that
+///   credentials aren't leaked to other servers. `Proxy-Authorization`
+///   is kept, unless the proxy changes.
+/// - Redirects from `https://` to `http://` URLs are refused with
+///   [`Error::InsecureRedirect`](enum.Error.html#variant.InsecureRedirect).
+///
+/// A callback can be added with [`with_callback`](#method.with_callback)
+/// to inspect each redirect before it's followed, and deny it. Denied
+/// redirects, and redirects that aren't followed at all (see
+/// [`none`](#method.none)), are returned to the caller as the response.
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> Result<(), minireq::Error> {
+/// use minireq::RedirectPolicy;
+///
+/// // Only follow redirects within example.com.
+/// let policy = RedirectPolicy::new()
+///     .with_callback(|attempt| attempt.next_url.starts_with("https://example.com/"));
+/// let response = minireq::get("https://example.com/old")
+///     .with_redirect_policy(policy)
+///     .send()?;
+/// # Ok(()) }
+/// ```
+#[derive(Clone)]
+pub struct RedirectPolicy {
+    follow: bool,
+    rewrite_post: bool,
+    allow_https_downgrade: bool,
+    callback: Option<Arc<RedirectCallback>>,
+}
+
+type RedirectCallback = dyn Fn(&RedirectAttempt) -> bool + Send + Sync;
+
+impl RedirectPolicy {
+    /// Creates the default policy, described above.
+    pub fn new() -> RedirectPolicy {
+        RedirectPolicy {
+            follow: true,
+            rewrite_post: false,
+            allow_https_downgrade: false,
+            callback: None,
+        }
+    }
+
+    /// Creates a policy that doesn't follow any redirects, and returns
+    /// them to the caller instead.
+    pub fn none() -> RedirectPolicy {
+        RedirectPolicy {
+            follow: false,
+            ..RedirectPolicy::new()
+        }
+    }
+
+    /// Sets whether `POST` requests that get a 301 or 302 redirect are
+    /// sent again as `GET` requests without a body, like browsers do.
+    /// Disabled by default, ie. the method and body are kept.
+    pub fn with_post_to_get(mut self, rewrite_post: bool) -> RedirectPolicy {
+        self.rewrite_post = rewrite_post;
+        self
+    }
+
+    /// Sets whether redirects from `https://` to `http://` URLs are
+    /// followed. Disabled by default.
+    pub fn with_https_downgrade(mut self, allow_https_downgrade: bool) -> RedirectPolicy {
+        self.allow_https_downgrade = allow_https_downgrade;
+        self
+    }
+
+    /// Sets a callback that's called before each redirect is followed,
+    /// after the policy's other checks have passed. If it returns
+    /// false, the redirect isn't followed and is returned as the
+    /// response instead.
+    pub fn with_callback<F>(mut self, callback: F) -> RedirectPolicy
+    where
+        F: Fn(&RedirectAttempt) -> bool + Send + Sync + 'static,
+    {
+        self.callback = Some(Arc::new(callback));
+        self
+    }
+
+    pub(crate) fn follows_redirects(&self) -> bool {
+        self.follow
+    }
+
+    /// Returns the method the redirected request should be sent with,
+    /// and whether it keeps the body.
+    pub(crate) fn redirected_method(&self, status_code: i32, method: &Method) -> (Method, bool) {
+        match (status_code, method) {
+            (303, method) if *method != Method::Head => (Method::Get, false),
+            (301 | 302, Method::Post) if self.rewrite_post => (Method::Get, false),
+            _ => (method.clone(), true),
+        }
+    }
+
+    pub(crate) fn allows_https_downgrade(&self) -> bool {
+        self.allow_https_downgrade
+    }
+
+    pub(crate) fn allows(&self, attempt: &RedirectAttempt) -> bool {
+        match self.callback {
+            Some(ref callback) => callback(attempt),
+            None => true,
+        }
+    }
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> RedirectPolicy {
+        RedirectPolicy::new()
+    }
+}
+
+impl PartialEq for RedirectPolicy {
+    fn eq(&self, other: &RedirectPolicy) -> bool {
+        let same_callback = match (&self.callback, &other.callback) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        self.follow == other.follow
+            && self.rewrite_post == other.rewrite_post
+            && self.allow_https_downgrade == other.allow_https_downgrade
+            && same_callback
+    }
+}
+
+impl Eq for RedirectPolicy {}
+
+impl fmt::Debug for RedirectPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RedirectPolicy")
+            .field("follow", &self.follow)
+            .field("rewrite_post", &self.rewrite_post)
+            .field("allow_https_downgrade", &self.allow_https_downgrade)
+            .field("callback", &self.callback.is_some())
+            .finish()
+    }
+}
+
+/// A redirect that's about to be followed, given to the callback set
+/// with [`RedirectPolicy::with_callback`].
+#[derive(Debug)]
+pub struct RedirectAttempt<'a> {
+    /// The status code of the redirect, eg. 301.
+    pub status_code: i32,
+    /// The URL that responded with the redirect.
+    pub url: &'a str,
+    /// The value of the redirect's `Location` header.
+    pub location: &'a str,
+    /// The URL the request would be sent to next, ie. `location`
+    /// resolved against `url`.
+    pub next_url: &'a str,
+    /// The method the request would be sent with next.
+    pub next_method: &'a Method,
+    /// The headers of the redirect response.
+    pub headers: &'a HeaderMap,
+    /// The amount of redirects that have been followed before this
+    /// one.
+    pub previous_redirects: usize,
+}
+
+/// A redirect that was followed on the way to a response, see
+/// [`Response::redirect_history`](struct.Response.html#method.redirect_history).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RedirectHop {
+    /// The URL that responded with the redirect.
+    pub url: String,
+    /// The status code of the redirect, eg. 301.
+    pub status_code: i32,
+    /// The value of the redirect's `Location` header.
+    pub location: String,
+    /// The headers of the redirect response.
+    pub headers: HeaderMap,
+}
@@ -1,15 +1,22 @@
 #[cfg(feature = "std")]
 use crate::connection::HttpStream;
-use crate::Error;
-use alloc::collections::BTreeMap;
+#[cfg(feature = "std")]
+use crate::decode::Decoder;
+#[cfg(feature = "std")]
+use crate::pool::{ConnectionPool, PoolKey};
+#[cfg(feature = "std")]
+use crate::redirect::RedirectHop;
+use crate::{Error, HeaderMap};
 use core::str;
 #[cfg(feature = "std")]
-use std::io::{self, BufReader, Bytes, Read};
+use std::io::{self, BufRead, BufReader, Read};
 
 #[cfg(feature = "std")]
 const BACKING_READ_BUFFER_LENGTH: usize = 16 * 1024;
 #[cfg(feature = "std")]
 const MAX_CONTENT_LENGTH: usize = 16 * 1024;
+#[cfg(feature = "std")]
+const MAX_DECODER_INPUT_LENGTH: usize = 16 * 1024;
 
 /// An HTTP response.
 ///
@@ -32,9 +39,9 @@ pub struct Response {
     pub status_code: i32,
     /// The reason phrase of the response, eg. "Not Found".
     pub reason_phrase: String,
-    /// The headers of the response. The header field names (the
-    /// keys) are all lowercase.
-    pub headers: BTreeMap<String, String>,
+    /// The headers of the response, in the order they were received.
+    /// The header field names are all lowercase.
+    pub headers: HeaderMap,
     /// The URL of the resource returned in this response. May differ from the
     /// request URL if it was redirected or typo corrections were applied (e.g.
     /// <http://example.com?foo=bar> would be corrected to
@@ -42,18 +49,20 @@ pub struct Response {
     pub url: String,
 
     body: Vec<u8>,
+    #[cfg(feature = "std")]
+    redirect_history: Vec<RedirectHop>,
+    #[cfg(feature = "std")]
+    attempts: u32,
 }
 
 impl Response {
     #[cfg(feature = "std")]
-    pub(crate) fn create(mut parent: ResponseLazy, is_head: bool) -> Result<Response, Error> {
+    pub(crate) fn create(mut parent: ResponseLazy) -> Result<Response, Error> {
         let mut body = Vec::new();
-        if !is_head && parent.status_code != 204 && parent.status_code != 304 {
-            for byte in &mut parent {
-                let (byte, length) = byte?;
-                body.reserve(length);
-                body.push(byte);
-            }
+        for byte in &mut parent {
+            let (byte, length) = byte?;
+            body.reserve(length);
+            body.push(byte);
         }
 
         let ResponseLazy {
@@ -61,6 +70,8 @@ impl Response {
             reason_phrase,
             headers,
             url,
+            redirect_history,
+            attempts,
             ..
         } = parent;
 
@@ -70,9 +81,63 @@ impl Response {
             headers,
             url,
             body,
+            redirect_history,
+            attempts,
         })
     }
 
+    /// Creates a response that was stored in a cache, rather than
+    /// received from a connection.
+    #[cfg(feature = "std")]
+    pub(crate) fn from_cache(
+        status_code: i32,
+        reason_phrase: String,
+        headers: HeaderMap,
+        url: String,
+        body: Vec<u8>,
+        attempts: u32,
+    ) -> Response {
+        Response {
+            status_code,
+            reason_phrase,
+            headers,
+            url,
+            body,
+            redirect_history: Vec::new(),
+            attempts,
+        }
+    }
+
+    /// Returns the redirects that were followed on the way to this
+    /// response, in the order they were followed. Empty if the request
+    /// wasn't redirected.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), minireq::Error> {
+    /// let response = minireq::get("http://example.com").send()?;
+    /// for hop in response.redirect_history() {
+    ///     println!("{} {} -> {}", hop.status_code, hop.url, hop.location);
+    /// }
+    /// println!("Ended up at {}", response.url);
+    /// # Ok(()) }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn redirect_history(&self) -> &[RedirectHop] {
+        &self.redirect_history
+    }
+
+    /// Returns how many times the request was sent to get this
+    /// response: 1, unless it was retried as allowed by its
+    /// [`RetryPolicy`](struct.RetryPolicy.html), or 0 if it was served
+    /// from a [`Cache`](struct.Cache.html) without contacting the
+    /// server.
+    #[cfg(feature = "std")]
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
     /// Returns the body as an `&str`.
     ///
     /// # Errors
@@ -142,6 +207,68 @@ impl Response {
     pub fn into_bytes(self) -> Vec<u8> {
         self.body
     }
+
+    /// Decodes an `application/x-www-form-urlencoded` body into its
+    /// key/value pairs, in order. `+` is decoded as a space, and keys
+    /// without a `=` get an empty value.
+    ///
+    /// # Errors
+    ///
+    /// Returns
+    /// [`InvalidUtf8InBody`](enum.Error.html#variant.InvalidUtf8InBody)
+    /// if the body, or any decoded key or value, is not valid UTF-8.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let url = "http://example.org/";
+    /// let response = minireq::get(url).send()?;
+    /// for (key, value) in response.form()? {
+    ///     println!("{} = {}", key, value);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    #[cfg(feature = "urlencoding")]
+    pub fn form(&self) -> Result<Vec<(String, String)>, Error> {
+        self.as_str()?
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                Ok((decode_form_component(key)?, decode_form_component(value)?))
+            })
+            .collect()
+    }
+
+    /// Deserializes the body from JSON.
+    ///
+    /// # Errors
+    ///
+    /// Returns
+    /// [`SerdeJsonError`](enum.Error.html#variant.SerdeJsonError) if
+    /// the body isn't valid JSON, or doesn't match `T`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), minireq::Error> {
+    /// # let url = "http://example.org/";
+    /// let names: Vec<String> = minireq::get(url).send()?.json()?;
+    /// # Ok(()) }
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T, Error> {
+        serde_json::from_slice(&self.body).map_err(Error::SerdeJsonError)
+    }
+}
+
+#[cfg(feature = "urlencoding")]
+fn decode_form_component(component: &str) -> Result<String, Error> {
+    match urlencoding::decode(&component.replace('+', " ")) {
+        Ok(decoded) => Ok(decoded.into_owned()),
+        Err(err) => Err(Error::InvalidUtf8InBody(err.utf8_error())),
+    }
 }
 
 /// An HTTP response, which is loaded lazily.
@@ -188,9 +315,9 @@ pub struct ResponseLazy {
     pub status_code: i32,
     /// The reason phrase of the response, eg. "Not Found".
     pub reason_phrase: String,
-    /// The headers of the response. The header field names (the
-    /// keys) are all lowercase.
-    pub headers: BTreeMap<String, String>,
+    /// The headers of the response, in the order they were received.
+    /// The header field names are all lowercase.
+    pub headers: HeaderMap,
     /// The URL of the resource returned in this response. May differ from the
     /// request URL if it was redirected or typo corrections were applied (e.g.
     /// <http://example.com?foo=bar> would be corrected to
@@ -200,27 +327,124 @@ pub struct ResponseLazy {
     stream: HttpStreamBytes,
     state: HttpStreamState,
     max_trailing_headers_size: Option<usize>,
+    keep_alive: bool,
+    pool: Option<(ConnectionPool, PoolKey)>,
+    decoder: Option<Decoder>,
+    pub(crate) redirect_history: Vec<RedirectHop>,
+    pub(crate) attempts: u32,
 }
 
+/// The buffered connection a response is read from, byte by byte.
+///
+/// Works like [`io::Bytes`], but the connection can be taken back out
+/// once the response has been read, so that it can be reused.
 #[cfg(feature = "std")]
-type HttpStreamBytes = Bytes<BufReader<HttpStream>>;
+struct HttpStreamBytes<S = HttpStream> {
+    reader: Option<BufReader<S>>,
+}
+
+#[cfg(feature = "std")]
+impl HttpStreamBytes {
+    fn new(stream: HttpStream) -> HttpStreamBytes {
+        HttpStreamBytes {
+            reader: Some(BufReader::with_capacity(BACKING_READ_BUFFER_LENGTH, stream)),
+        }
+    }
+
+    /// Returns up to `max` of the buffered bytes, filling the buffer
+    /// from the connection first if it's empty. Returns no bytes once
+    /// the connection is closed.
+    fn next_buffered(&mut self, max: usize) -> io::Result<Vec<u8>> {
+        let reader = match self.reader.as_mut() {
+            Some(reader) => reader,
+            None => return Ok(Vec::new()),
+        };
+        loop {
+            match reader.fill_buf() {
+                Ok(buf) => {
+                    let bytes = buf[..buf.len().min(max)].to_vec();
+                    reader.consume(bytes.len());
+                    return Ok(bytes);
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Takes the connection out, unless there are buffered bytes that
+    /// haven't been read yet, in which case the connection is in an
+    /// unknown state and is just closed.
+    fn take_stream(&mut self) -> Option<HttpStream> {
+        let reader = self.reader.take()?;
+        if reader.buffer().is_empty() {
+            Some(reader.into_inner())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: Read> Iterator for HttpStreamBytes<S> {
+    type Item = io::Result<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let reader = self.reader.as_mut()?;
+        loop {
+            match reader.fill_buf() {
+                Ok(buf) => {
+                    let byte = *buf.first()?;
+                    reader.consume(1);
+                    return Some(Ok(byte));
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
 
 #[cfg(feature = "std")]
 impl ResponseLazy {
     pub(crate) fn from_stream(
         stream: HttpStream,
+        is_head: bool,
+        decompress: bool,
         max_headers_size: Option<usize>,
         max_status_line_len: Option<usize>,
     ) -> Result<ResponseLazy, Error> {
-        let mut stream = BufReader::with_capacity(BACKING_READ_BUFFER_LENGTH, stream).bytes();
+        let mut stream = HttpStreamBytes::new(stream);
         let ResponseMetadata {
             status_code,
             reason_phrase,
-            headers,
-            state,
+            mut headers,
+            mut state,
             max_trailing_headers_size,
+            keep_alive,
         } = read_metadata(&mut stream, max_headers_size, max_status_line_len)?;
 
+        // Responses to HEAD requests, and 1xx, 204 and 304 responses
+        // never have a body, regardless of their headers. See RFC 7230
+        // section 3.3.3.
+        if is_head || (100..200).contains(&status_code) || status_code == 204 || status_code == 304
+        {
+            state = HttpStreamState::ContentLength(0);
+        }
+
+        // The headers describe the encoded body, so they're removed
+        // when it's decoded.
+        let has_body = !matches!(state, HttpStreamState::ContentLength(0));
+        let decoder = if decompress && has_body {
+            Decoder::new(&headers)
+        } else {
+            None
+        };
+        if decoder.is_some() {
+            headers.remove("content-encoding");
+            headers.remove("content-length");
+        }
+
         Ok(ResponseLazy {
             status_code,
             reason_phrase,
@@ -229,8 +453,79 @@ impl ResponseLazy {
             stream,
             state,
             max_trailing_headers_size,
+            keep_alive,
+            pool: None,
+            decoder,
+            redirect_history: Vec::new(),
+            attempts: 1,
         })
     }
+
+    /// Returns the redirects that were followed on the way to this
+    /// response, in the order they were followed. Empty if the request
+    /// wasn't redirected. See
+    /// [`Response::redirect_history`](struct.Response.html#method.redirect_history).
+    pub fn redirect_history(&self) -> &[RedirectHop] {
+        &self.redirect_history
+    }
+
+    /// Returns how many times the request was sent to get this
+    /// response. See
+    /// [`Response::attempts`](struct.Response.html#method.attempts).
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Deserializes the body from JSON as it's being read, without
+    /// loading the whole body into memory first.
+    ///
+    /// # Errors
+    ///
+    /// Returns
+    /// [`SerdeJsonError`](enum.Error.html#variant.SerdeJsonError) if
+    /// the body isn't valid JSON, doesn't match `T`, or couldn't be
+    /// read.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), minireq::Error> {
+    /// # let url = "http://example.org/";
+    /// let names: Vec<String> = minireq::get(url).send_lazy()?.json()?;
+    /// # Ok(()) }
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::de::DeserializeOwned>(self) -> Result<T, Error> {
+        serde_json::from_reader(self).map_err(Error::SerdeJsonError)
+    }
+
+    /// Makes this response put its connection into `pool` once the
+    /// body has been read, if the connection can be reused.
+    pub(crate) fn return_connection_to(&mut self, pool: ConnectionPool, key: PoolKey) {
+        if self.keep_alive {
+            self.pool = Some((pool, key));
+            // The body might be empty, in which case there's nothing
+            // to wait for.
+            self.release_connection_if_done();
+        }
+    }
+
+    fn release_connection_if_done(&mut self) {
+        let done = match self.state {
+            HttpStreamState::EndOnClose => false,
+            HttpStreamState::ContentLength(length) => length == 0,
+            HttpStreamState::Chunked(expecting_chunks, length, _) => {
+                !expecting_chunks && length == 0
+            }
+        };
+        if done {
+            if let Some((pool, key)) = self.pool.take() {
+                if let Some(stream) = self.stream.take_stream() {
+                    pool.release(key, stream);
+                }
+            }
+        }
+    }
 }
 
 #[cfg(feature = "std")]
@@ -238,8 +533,20 @@ impl Iterator for ResponseLazy {
     type Item = Result<(u8, usize), Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.decoder.is_some() {
+            self.next_decoded()
+        } else {
+            self.next_raw()
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ResponseLazy {
+    /// Returns the next byte of the body as it was received.
+    fn next_raw(&mut self) -> Option<<ResponseLazy as Iterator>::Item> {
         use HttpStreamState::*;
-        match self.state {
+        let result = match self.state {
             EndOnClose => read_until_closed(&mut self.stream),
             ContentLength(ref mut length) => read_with_content_length(&mut self.stream, length),
             Chunked(ref mut expecting_chunks, ref mut length, ref mut content_length) => {
@@ -252,6 +559,73 @@ impl Iterator for ResponseLazy {
                     self.max_trailing_headers_size,
                 )
             }
+        };
+        if self.pool.is_some() && !matches!(result, Some(Err(_))) {
+            self.release_connection_if_done();
+        }
+        result
+    }
+
+    /// Returns the next byte of the decoded body, decoding it a buffer
+    /// at a time, and feeding the decoder with the received bytes that
+    /// are available without blocking whenever it runs out.
+    fn next_decoded(&mut self) -> Option<<ResponseLazy as Iterator>::Item> {
+        loop {
+            let decoder = self.decoder.as_mut()?;
+            if let Some((byte, ready)) = decoder.next_byte() {
+                return Some(Ok((byte, ready.min(MAX_CONTENT_LENGTH))));
+            }
+            if decoder.is_finished() {
+                return None;
+            }
+
+            if decoder.needs_input() {
+                let (input, ended) = match self.next_decoder_input() {
+                    Ok(input) => input,
+                    Err(err) => return Some(Err(err)),
+                };
+                self.decoder.as_mut()?.push_input(input, ended);
+            }
+
+            let decoder = self.decoder.as_mut()?;
+            if let Err(err) = decoder.decode() {
+                return Some(Err(Error::from(err)));
+            }
+            // Chunked bodies get a Content-Length once they've been
+            // read, which should be the decoded length.
+            if decoder.is_finished() && self.headers.contains_key("content-length") {
+                let length = decoder.decoded_length().to_string();
+                self.headers.insert("content-length", length);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ResponseLazy {
+    /// Returns the next piece of the body as it was received, for the
+    /// decoder, and whether it's the last one.
+    fn next_decoder_input(&mut self) -> Result<(Vec<u8>, bool), Error> {
+        if let HttpStreamState::EndOnClose = self.state {
+            // Every byte is reported as the last one ready, so the
+            // buffered bytes are taken as they are instead.
+            let input = self.stream.next_buffered(MAX_DECODER_INPUT_LENGTH)?;
+            let ended = input.is_empty();
+            return Ok((input, ended));
+        }
+
+        let mut input = Vec::new();
+        loop {
+            match self.next_raw() {
+                Some(Ok((byte, length))) => {
+                    input.push(byte);
+                    if length <= 1 || input.len() >= MAX_DECODER_INPUT_LENGTH {
+                        return Ok((input, false));
+                    }
+                }
+                Some(Err(err)) => return Err(err),
+                None => return Ok((input, true)),
+            }
         }
     }
 }
@@ -265,6 +639,7 @@ impl Read for ResponseLazy {
             // so it is ignored.
             let (byte, _) = res.map_err(|e| match e {
                 Error::IoError(e) => e,
+                Error::Timeout(kind) => io::Error::new(io::ErrorKind::TimedOut, kind),
                 _ => io::Error::new(io::ErrorKind::Other, e),
             })?;
 
@@ -287,7 +662,7 @@ fn read_until_closed(bytes: &mut HttpStreamBytes) -> Option<<ResponseLazy as Ite
     if let Some(byte) = bytes.next() {
         match byte {
             Ok(byte) => Some(Ok((byte, 1))),
-            Err(err) => Some(Err(Error::IoError(err))),
+            Err(err) => Some(Err(Error::from(err))),
         }
     } else {
         None
@@ -306,7 +681,7 @@ fn read_with_content_length(
             match byte {
                 // Cap Content-Length to 16KiB, to avoid out-of-memory issues.
                 Ok(byte) => return Some(Ok((byte, (*content_length).min(MAX_CONTENT_LENGTH) + 1))),
-                Err(err) => return Some(Err(Error::IoError(err))),
+                Err(err) => return Some(Err(Error::from(err))),
             }
         }
     }
@@ -316,7 +691,7 @@ fn read_with_content_length(
 #[cfg(feature = "std")]
 fn read_trailers(
     bytes: &mut HttpStreamBytes,
-    headers: &mut BTreeMap<String, String>,
+    headers: &mut HeaderMap,
     mut max_headers_size: Option<usize>,
 ) -> Result<(), Error> {
     loop {
@@ -325,7 +700,7 @@ fn read_trailers(
             *max_headers_size -= trailer_line.len() + 2;
         }
         if let Some((header, value)) = parse_header(trailer_line) {
-            headers.insert(header, value);
+            headers.append(header, value);
         } else {
             break;
         }
@@ -336,7 +711,7 @@ fn read_trailers(
 #[cfg(feature = "std")]
 fn read_chunked(
     bytes: &mut HttpStreamBytes,
-    headers: &mut BTreeMap<String, String>,
+    headers: &mut HeaderMap,
     expecting_more_chunks: &mut bool,
     chunk_length: &mut usize,
     content_length: &mut usize,
@@ -380,7 +755,7 @@ fn read_chunked(
             }
 
             *expecting_more_chunks = false;
-            headers.insert("content-length".to_string(), (*content_length).to_string());
+            headers.insert("content-length", (*content_length).to_string());
             headers.remove("transfer-encoding");
             return None;
         }
@@ -408,7 +783,7 @@ fn read_chunked(
 
                     return Some(Ok((byte, (*chunk_length).min(MAX_CONTENT_LENGTH) + 1)));
                 }
-                Err(err) => return Some(Err(Error::IoError(err))),
+                Err(err) => return Some(Err(Error::from(err))),
             }
         }
     }
@@ -432,6 +807,42 @@ enum HttpStreamState {
     Chunked(bool, usize, usize),
 }
 
+/// The status line and headers of a proxy's response to a `CONNECT`
+/// request.
+#[cfg(feature = "proxy")]
+pub(crate) struct ConnectResponse {
+    pub(crate) status_code: i32,
+    pub(crate) reason_phrase: String,
+    pub(crate) headers: HeaderMap,
+}
+
+#[cfg(feature = "proxy")]
+impl ConnectResponse {
+    /// Reads the response up to the blank line after the headers. The
+    /// stream is read one byte at a time, so that the bytes after it,
+    /// which belong to the tunnel, are left unread.
+    pub(crate) fn read(
+        stream: &mut HttpStream,
+        max_headers_size: Option<usize>,
+        max_status_line_len: Option<usize>,
+    ) -> Result<ConnectResponse, Error> {
+        let mut reader = BufReader::with_capacity(1, stream);
+        if reader.fill_buf()?.is_empty() {
+            // The proxy closed the connection without answering.
+            return Err(Error::ProxyConnect);
+        }
+        let mut stream = HttpStreamBytes {
+            reader: Some(reader),
+        };
+        let metadata = read_metadata(&mut stream, max_headers_size, max_status_line_len)?;
+        Ok(ConnectResponse {
+            status_code: metadata.status_code,
+            reason_phrase: metadata.reason_phrase,
+            headers: metadata.headers,
+        })
+    }
+}
+
 // This struct is just used in the Response and ResponseLazy
 // constructors, but not in their structs, for api-cleanliness
 // reasons. (Eg. response.status_code is much cleaner than
@@ -440,21 +851,25 @@ enum HttpStreamState {
 struct ResponseMetadata {
     status_code: i32,
     reason_phrase: String,
-    headers: BTreeMap<String, String>,
+    headers: HeaderMap,
     state: HttpStreamState,
     max_trailing_headers_size: Option<usize>,
+    keep_alive: bool,
 }
 
 #[cfg(feature = "std")]
-fn read_metadata(
-    stream: &mut HttpStreamBytes,
+fn read_metadata<S: Read>(
+    stream: &mut HttpStreamBytes<S>,
     mut max_headers_size: Option<usize>,
     max_status_line_len: Option<usize>,
 ) -> Result<ResponseMetadata, Error> {
     let line = read_line(stream, max_status_line_len, Error::StatusLineOverflow)?;
     let (status_code, reason_phrase) = parse_status_line(&line);
+    // HTTP/1.0 connections are closed after the response by default,
+    // HTTP/1.1 ones are kept open.
+    let mut keep_alive = !line.starts_with("HTTP/1.0");
 
-    let mut headers = BTreeMap::new();
+    let mut headers = HeaderMap::new();
     loop {
         let line = read_line(stream, max_headers_size, Error::HeadersOverflow)?;
         if line.is_empty() {
@@ -464,8 +879,8 @@ fn read_metadata(
         if let Some(ref mut max_headers_size) = max_headers_size {
             *max_headers_size -= line.len() + 2;
         }
-        if let Some(header) = parse_header(line) {
-            headers.insert(header.0, header.1);
+        if let Some((header, value)) = parse_header(line) {
+            headers.append(header, value);
         }
     }
 
@@ -479,11 +894,28 @@ fn read_metadata(
             chunked = true;
         }
 
-        // Handle the Content-Length header
+        // Handle the Connection header
+        if header.to_lowercase().trim() == "connection" {
+            match value.to_lowercase().trim() {
+                "close" => keep_alive = false,
+                "keep-alive" => keep_alive = true,
+                _ => {}
+            }
+        }
+
+        // Handle the Content-Length header. Repeated values (in one
+        // header or several) must all be the same, see RFC 9112 section
+        // 6.3, or the body's end would be ambiguous.
         if header.to_lowercase().trim() == "content-length" {
-            match str::parse::<usize>(value.trim()) {
-                Ok(length) => content_length = Some(length),
-                Err(_) => return Err(Error::MalformedContentLength),
+            for length in value.split(',') {
+                let length = match str::parse::<usize>(length.trim()) {
+                    Ok(length) => length,
+                    Err(_) => return Err(Error::MalformedContentLength),
+                };
+                if matches!(content_length, Some(other) if other != length) {
+                    return Err(Error::MalformedContentLength);
+                }
+                content_length = Some(length);
             }
         }
     }
@@ -502,12 +934,13 @@ fn read_metadata(
         headers,
         state,
         max_trailing_headers_size: max_headers_size,
+        keep_alive,
     })
 }
 
 #[cfg(feature = "std")]
-fn read_line(
-    stream: &mut HttpStreamBytes,
+fn read_line<S: Read>(
+    stream: &mut HttpStreamBytes<S>,
     max_len: Option<usize>,
     overflow_error: Error,
 ) -> Result<String, Error> {
@@ -529,7 +962,7 @@ fn read_line(
                     bytes.push(byte);
                 }
             }
-            Err(err) => return Err(Error::IoError(err)),
+            Err(err) => return Err(Error::from(err)),
         }
     }
     String::from_utf8(bytes).map_err(|_error| Error::InvalidUtf8InResponse)
@@ -0,0 +1,337 @@
+use crate::date::parse_http_date;
+use crate::{Error, Method, ResponseLazy, TimeoutKind};
+use alloc::sync::Arc;
+use core::fmt;
+use core::time::Duration;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::time::SystemTime;
+
+/// Decides whether, and when, a failed [`Request`](struct.Request.html)
+/// is sent again. Set with
+/// [`Request::with_retry_policy`](struct.Request.html#method.with_retry_policy),
+/// requests aren't retried without one.
+///
+/// With the default policy:
+///
+/// - A request is sent at most 3 times.
+/// - It's retried if it fails with a connection error (eg. the
+///   connection was refused or reset, the host couldn't be resolved, or
+///   connecting timed out), or if the response's status code is 429,
+///   502, 503 or 504.
+/// - Only requests with idempotent methods (`GET`, `HEAD`, `PUT`,
+///   `DELETE`, `OPTIONS` and `TRACE`) are retried.
+/// - The first retry waits for 100 milliseconds, and each one after
+///   that waits twice as long as the previous one, up to 10 seconds.
+///   Each wait is randomly shortened by up to half, so that many
+///   clients don't all retry at the same moment.
+/// - If the response has a `Retry-After` header, it's waited for
+///   instead. If it asks for a longer wait than the maximum backoff,
+///   the response is returned as-is.
+///
+/// Requests are never retried past their
+/// [total timeout](struct.Request.html#method.with_total_timeout), and
+/// requests with a body that has already been read from a reader (see
+/// [`Request::with_body_reader`](struct.Request.html#method.with_body_reader))
+/// aren't retried, since the body can't be sent again. The number of
+/// attempts it took is available from
+/// [`Response::attempts`](struct.Response.html#method.attempts).
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> Result<(), minireq::Error> {
+/// use minireq::RetryPolicy;
+/// use std::time::Duration;
+///
+/// let policy = RetryPolicy::new()
+///     .with_max_attempts(5)
+///     .with_backoff(Duration::from_millis(250), Duration::from_secs(30));
+/// let response = minireq::get("http://example.com")
+///     .with_retry_policy(policy)
+///     .send()?;
+/// println!("Succeeded after {} attempts", response.attempts());
+/// # Ok(()) }
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+    all_methods: bool,
+    predicate: Option<Arc<RetryPredicate>>,
+}
+
+type RetryPredicate = dyn Fn(&RetryOutcome) -> bool + Send + Sync;
+
+impl RetryPolicy {
+    /// Creates the default policy, described above.
+    pub fn new() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            jitter: true,
+            all_methods: false,
+            predicate: None,
+        }
+    }
+
+    /// Sets how many times the request is sent at most, including the
+    /// first attempt. 3 by default.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> RetryPolicy {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Sets how long to wait before the first retry, and the longest
+    /// wait between retries (including ones asked for with
+    /// `Retry-After`). 100 milliseconds and 10 seconds by default.
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> RetryPolicy {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Sets whether the waits between retries are randomly shortened
+    /// by up to half. Enabled by default.
+    pub fn with_jitter(mut self, jitter: bool) -> RetryPolicy {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets whether requests with methods that aren't idempotent, like
+    /// `POST` and `PATCH`, are retried too. Disabled by default, since
+    /// the server might have acted on a request before it failed.
+    pub fn with_all_methods(mut self, all_methods: bool) -> RetryPolicy {
+        self.all_methods = all_methods;
+        self
+    }
+
+    /// Replaces the default check for which responses and errors are
+    /// retried. The callback is called after each attempt (except the
+    /// last one), and the request is retried if it returns true.
+    pub fn with_retry_if<F>(mut self, predicate: F) -> RetryPolicy
+    where
+        F: Fn(&RetryOutcome) -> bool + Send + Sync + 'static,
+    {
+        self.predicate = Some(Arc::new(predicate));
+        self
+    }
+
+    pub(crate) fn retries_method(&self, method: &Method) -> bool {
+        self.all_methods
+            || matches!(
+                method,
+                Method::Get
+                    | Method::Head
+                    | Method::Put
+                    | Method::Delete
+                    | Method::Options
+                    | Method::Trace
+            )
+    }
+
+    /// Returns how long to wait before sending the request again, after
+    /// `attempts` attempts that ended in `outcome`, or None if it
+    /// shouldn't be retried.
+    pub(crate) fn retry_delay(&self, attempts: u32, outcome: &RetryOutcome) -> Option<Duration> {
+        if attempts >= self.max_attempts || !self.should_retry(outcome) {
+            return None;
+        }
+        if let RetryOutcome::Response(response) = outcome {
+            if let Some(delay) = response.headers.get("retry-after").and_then(retry_after) {
+                return Some(delay).filter(|delay| *delay <= self.max_backoff);
+            }
+        }
+        let backoff = self
+            .initial_backoff
+            .checked_mul(2u32.saturating_pow(attempts - 1))
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff));
+        if self.jitter {
+            Some(backoff.mul_f64(1.0 - random_fraction() / 2.0))
+        } else {
+            Some(backoff)
+        }
+    }
+
+    fn should_retry(&self, outcome: &RetryOutcome) -> bool {
+        if let Some(ref predicate) = self.predicate {
+            return predicate(outcome);
+        }
+        match outcome {
+            RetryOutcome::Response(response) => {
+                matches!(response.status_code, 429 | 502 | 503 | 504)
+            }
+            RetryOutcome::Error(Error::AddressNotFound)
+            | RetryOutcome::Error(Error::Timeout(TimeoutKind::Connect)) => true,
+            RetryOutcome::Error(Error::IoError(err)) => matches!(
+                err.kind(),
+                io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::NotConnected
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::UnexpectedEof
+            ),
+            RetryOutcome::Error(_) => false,
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy::new()
+    }
+}
+
+impl PartialEq for RetryPolicy {
+    fn eq(&self, other: &RetryPolicy) -> bool {
+        let same_predicate = match (&self.predicate, &other.predicate) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        self.max_attempts == other.max_attempts
+            && self.initial_backoff == other.initial_backoff
+            && self.max_backoff == other.max_backoff
+            && self.jitter == other.jitter
+            && self.all_methods == other.all_methods
+            && same_predicate
+    }
+}
+
+impl Eq for RetryPolicy {}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("initial_backoff", &self.initial_backoff)
+            .field("max_backoff", &self.max_backoff)
+            .field("jitter", &self.jitter)
+            .field("all_methods", &self.all_methods)
+            .field("predicate", &self.predicate.is_some())
+            .finish()
+    }
+}
+
+/// How an attempt at sending a request ended, given to the callback
+/// set with [`RetryPolicy::with_retry_if`].
+pub enum RetryOutcome<'a> {
+    /// A response was received. Its body hasn't been read.
+    Response(&'a ResponseLazy),
+    /// Sending the request failed.
+    Error(&'a Error),
+}
+
+impl fmt::Debug for RetryOutcome<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RetryOutcome::Response(response) => f
+                .debug_tuple("Response")
+                .field(&response.status_code)
+                .finish(),
+            RetryOutcome::Error(err) => f.debug_tuple("Error").field(err).finish(),
+        }
+    }
+}
+
+/// Parses a `Retry-After` header, which is either an amount of seconds
+/// or an HTTP-date.
+fn retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = parse_http_date(value)?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+/// Returns a random number between 0 and 1, without depending on a
+/// random number generator crate.
+fn random_fraction() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(elapsed) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(elapsed.as_nanos());
+    }
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{retry_after, RetryOutcome, RetryPolicy};
+    use crate::{Error, Method};
+    use core::time::Duration;
+    use std::io;
+
+    #[test]
+    fn backoff() {
+        let policy = RetryPolicy::new()
+            .with_max_attempts(10)
+            .with_backoff(Duration::from_millis(100), Duration::from_secs(1))
+            .with_jitter(false);
+        let reset = Error::IoError(io::ErrorKind::ConnectionReset.into());
+        let delays = (1..=10)
+            .map(|attempts| policy.retry_delay(attempts, &RetryOutcome::Error(&reset)))
+            .collect::<Vec<_>>();
+        let ms = |ms| Some(Duration::from_millis(ms));
+        assert_eq!(
+            delays,
+            [
+                ms(100),
+                ms(200),
+                ms(400),
+                ms(800),
+                ms(1000),
+                ms(1000),
+                ms(1000),
+                ms(1000),
+                ms(1000),
+                None
+            ]
+        );
+
+        let jittered = RetryPolicy::new().retry_delay(2, &RetryOutcome::Error(&reset));
+        assert!(jittered >= ms(100) && jittered <= ms(200));
+    }
+
+    #[test]
+    fn retried_errors() {
+        let policy = RetryPolicy::new();
+        let retried = |err: Error| policy.retry_delay(1, &RetryOutcome::Error(&err)).is_some();
+        assert!(retried(Error::AddressNotFound));
+        assert!(retried(Error::IoError(
+            io::ErrorKind::ConnectionRefused.into()
+        )));
+        assert!(!retried(Error::IoError(
+            io::ErrorKind::PermissionDenied.into()
+        )));
+        assert!(!retried(Error::HeadersOverflow));
+    }
+
+    #[test]
+    fn idempotent_methods() {
+        let policy = RetryPolicy::new();
+        assert!(policy.retries_method(&Method::Get));
+        assert!(policy.retries_method(&Method::Put));
+        assert!(!policy.retries_method(&Method::Post));
+        assert!(!policy.retries_method(&Method::Custom("PURGE".to_string())));
+        assert!(policy.with_all_methods(true).retries_method(&Method::Post));
+    }
+
+    #[test]
+    fn retry_after_formats() {
+        assert_eq!(retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(
+            retry_after("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(retry_after("soon"), None);
+    }
+}
@@ -1,12 +1,15 @@
 extern crate minireq;
 extern crate tiny_http;
 use self::tiny_http::{Header, Method, Response, Server, StatusCode};
+use std::collections::BTreeMap;
 use std::str::FromStr;
-use std::sync::{Arc, Once};
+use std::sync::{Arc, Mutex, Once};
 use std::thread;
 use std::time::Duration;
 
 static INIT: Once = Once::new();
+static FLAKY_ATTEMPTS: Mutex<Option<BTreeMap<String, u32>>> = Mutex::new(None);
+static CACHED_HITS: Mutex<Option<BTreeMap<String, u32>>> = Mutex::new(None);
 
 pub fn setup() {
     INIT.call_once(|| {
@@ -39,6 +42,194 @@ pub fn setup() {
                         request.respond(Response::from_string("No header!")).ok();
                     }
 
+                    Method::Get if url == "/remote_port" => {
+                        let port = request.remote_addr().map(|addr| addr.port()).unwrap_or(0);
+                        request
+                            .respond(Response::from_string(port.to_string()))
+                            .ok();
+                    }
+
+                    Method::Get if url == "/multi_header" => {
+                        let response = Response::empty(200)
+                            .with_header(Header::from_str("Set-Cookie: a=1").unwrap())
+                            .with_header(Header::from_str("Set-Cookie: b=2").unwrap());
+                        request.respond(response).ok();
+                    }
+
+                    Method::Get if url == "/compressed" || url == "/compressed_stacked" => {
+                        let accept_encoding = headers
+                            .iter()
+                            .find(|header| header.field.equiv("Accept-Encoding"))
+                            .map(|header| header.value.to_string())
+                            .unwrap_or_default();
+                        let (body, content_encoding) = if url == "/compressed_stacked" {
+                            (STACKED_COMPRESSED_BODY, "deflate, gzip")
+                        } else {
+                            (GZIP_COMPRESSED_BODY, "gzip")
+                        };
+                        let response = Response::from_data(body)
+                            .with_header(
+                                Header::from_bytes("Content-Encoding", content_encoding).unwrap(),
+                            )
+                            .with_header(
+                                Header::from_bytes("X-Accept-Encoding", accept_encoding).unwrap(),
+                            );
+                        request.respond(response).ok();
+                    }
+
+                    _ if url.starts_with("/redirect_status/") => {
+                        // eg. /redirect_status/308?/echo
+                        let (status, location) =
+                            url["/redirect_status/".len()..].split_once('?').unwrap();
+                        let response = Response::empty(status.parse::<u16>().unwrap())
+                            .with_header(Header::from_bytes("Location", location).unwrap());
+                        request.respond(response).ok();
+                    }
+
+                    _ if url.starts_with("/flaky/") => {
+                        // eg. /flaky/name?2&120 responds with 503 and
+                        // Retry-After: 120 twice, and then echoes the body
+                        let (name, query) = url["/flaky/".len()..].split_once('?').unwrap();
+                        let (failures, retry_after) = match query.split_once('&').unwrap() {
+                            (failures, "past_date") => (failures, "Sun, 06 Nov 1994 08:49:37 GMT"),
+                            query => query,
+                        };
+                        let attempts = {
+                            let mut counts = FLAKY_ATTEMPTS.lock().unwrap();
+                            let counts = counts.get_or_insert_with(BTreeMap::new);
+                            let count = counts.entry(name.to_string()).or_insert(0);
+                            *count += 1;
+                            *count
+                        };
+                        if attempts <= failures.parse::<u32>().unwrap() {
+                            let response = Response::empty(503).with_header(
+                                Header::from_bytes("Retry-After", retry_after).unwrap(),
+                            );
+                            request.respond(response).ok();
+                        } else {
+                            let response = Response::from_string(format!("j: {}", content));
+                            request.respond(response).ok();
+                        }
+                    }
+
+                    _ if url.starts_with("/cached/") => {
+                        // eg. /cached/etag/name responds with "hits: 1"
+                        // (counting every request to it), cacheable in the
+                        // way the second path segment describes
+                        let (kind, name) = url["/cached/".len()..].split_once('/').unwrap();
+                        let hits = {
+                            let mut counts = CACHED_HITS.lock().unwrap();
+                            let counts = counts.get_or_insert_with(BTreeMap::new);
+                            let count = counts.entry(url.clone()).or_insert(0);
+                            *count += 1;
+                            *count
+                        };
+                        let if_none_match = headers
+                            .iter()
+                            .find(|header| header.field.equiv("If-None-Match"))
+                            .map(|header| header.value.to_string());
+                        let (cache_control, etag) = match kind {
+                            "max_age" => ("max-age=60", None),
+                            "vary_cookie" => ("max-age=60", None),
+                            "etag" => ("no-cache", Some(format!("\"{}\"", name))),
+                            "no_store" => ("no-store", None),
+                            _ => panic!("unknown cache kind {}", kind),
+                        };
+                        let mut response = if etag.is_some() && etag == if_none_match {
+                            Response::from_string("").with_status_code(304)
+                        } else {
+                            Response::from_string(format!("hits: {}", hits))
+                        };
+                        response = response.with_header(
+                            Header::from_bytes("Cache-Control", cache_control).unwrap(),
+                        );
+                        if let Some(etag) = etag {
+                            response =
+                                response.with_header(Header::from_bytes("ETag", etag).unwrap());
+                        }
+                        if kind == "vary_cookie" {
+                            response =
+                                response.with_header(Header::from_bytes("Vary", "Cookie").unwrap());
+                        }
+                        request.respond(response).ok();
+                    }
+
+                    Method::Get if url.starts_with("/redirect_chain/") => {
+                        // eg. /redirect_chain/3 redirects three times before
+                        // ending up at /a
+                        let remaining = url["/redirect_chain/".len()..].parse::<u32>().unwrap();
+                        let location = match remaining {
+                            0 => "/a".to_string(),
+                            n => format!("/redirect_chain/{}", n - 1),
+                        };
+                        let response = Response::empty(302)
+                            .with_header(Header::from_bytes("Location", location).unwrap());
+                        request.respond(response).ok();
+                    }
+
+                    Method::Get if url == "/redirect_cross_origin" => {
+                        // Requested through 127.0.0.1, so this is another origin.
+                        let location = "http://localhost:35562/credentials";
+                        let response = Response::empty(302)
+                            .with_header(Header::from_bytes("Location", location).unwrap());
+                        request.respond(response).ok();
+                    }
+
+                    Method::Get if url == "/cookie_redirect" => {
+                        let response = Response::empty(302)
+                            .with_header(Header::from_bytes("Location", "/credentials").unwrap())
+                            .with_header(
+                                Header::from_bytes("Set-Cookie", "session=abc; Path=/; HttpOnly")
+                                    .unwrap(),
+                            )
+                            .with_header(
+                                Header::from_bytes("Set-Cookie", "scoped=1; Path=/other").unwrap(),
+                            );
+                        request.respond(response).ok();
+                    }
+
+                    Method::Get if url == "/credentials" => {
+                        let get = |name: &str| {
+                            headers
+                                .iter()
+                                .find(|header| {
+                                    header.field.as_str().as_str().eq_ignore_ascii_case(name)
+                                })
+                                .map(|header| header.value.to_string())
+                                .unwrap_or_default()
+                        };
+                        let response = Response::from_string(format!(
+                            "authorization={} cookie={} x-other={}",
+                            get("Authorization"),
+                            get("Cookie"),
+                            get("X-Other")
+                        ));
+                        request.respond(response).ok();
+                    }
+
+                    Method::Get if url == "/digest" => {
+                        let authorization = headers
+                            .iter()
+                            .find(|header| header.field.equiv("Authorization"))
+                            .map(|header| header.value.to_string())
+                            .filter(|value| value.starts_with("Digest "));
+                        let response = match authorization {
+                            Some(authorization) => Response::from_string(authorization),
+                            None => Response::from_string("challenged")
+                                .with_status_code(401)
+                                .with_header(
+                                    Header::from_bytes(
+                                        "WWW-Authenticate",
+                                        "Basic realm=\"test\", Digest realm=\"test\", \
+                                         qop=\"auth\", algorithm=SHA-256, nonce=\"abc\", \
+                                         opaque=\"xyz\"",
+                                    )
+                                    .unwrap(),
+                                ),
+                        };
+                        request.respond(response).ok();
+                    }
+
                     Method::Get if url == "/slow_a" => {
                         thread::sleep(Duration::from_secs(2));
                         let response = Response::from_string(format!("j: {}", content));
@@ -128,6 +319,24 @@ pub fn setup() {
                         request.respond(Response::from_string(content)).ok();
                     }
 
+                    _ if url == "/request_echo" => {
+                        let framing = headers
+                            .iter()
+                            .filter(|header| {
+                                header.field.equiv("Content-Length")
+                                    || header.field.equiv("Transfer-Encoding")
+                            })
+                            .map(|header| header.to_string())
+                            .collect::<Vec<_>>();
+                        let response = Response::from_string(format!(
+                            "method={} framing={} body={}",
+                            request.method(),
+                            framing.join(", "),
+                            content
+                        ));
+                        request.respond(response).ok();
+                    }
+
                     Method::Head if url == "/b" => {
                         request.respond(Response::empty(418)).ok();
                     }
@@ -171,6 +380,23 @@ pub fn setup() {
     });
 }
 
+pub const COMPRESSED_BODY: &[u8] = b"Hello, compressed world! Hello, compressed world!";
+
+/// `COMPRESSED_BODY` with the gzip coding.
+pub const GZIP_COMPRESSED_BODY: &[u8] = &[
+    0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xf3, 0x48, 0xcd, 0xc9, 0xc9, 0xd7,
+    0x51, 0x48, 0xce, 0xcf, 0x2d, 0x28, 0x4a, 0x2d, 0x2e, 0x4e, 0x4d, 0x51, 0x28, 0xcf, 0x2f, 0xca,
+    0x49, 0x51, 0x54, 0xf0, 0xc0, 0x21, 0x01, 0x00, 0xad, 0x35, 0x51, 0xc7, 0x31, 0x00, 0x00, 0x00,
+];
+
+/// `COMPRESSED_BODY` with the deflate coding, then the gzip coding.
+const STACKED_COMPRESSED_BODY: &[u8] = &[
+    0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xab, 0x98, 0xf3, 0xd9, 0xe3, 0xec,
+    0xc9, 0x93, 0xd7, 0x03, 0x3d, 0xce, 0x9d, 0xd7, 0xd5, 0xf0, 0xd2, 0xd5, 0xf3, 0xf3, 0x0d, 0xd4,
+    0x38, 0xaf, 0x7f, 0xca, 0x33, 0x30, 0xe4, 0xc3, 0x01, 0x45, 0x46, 0x86, 0x7d, 0x0e, 0x82, 0x77,
+    0x01, 0x7f, 0xdc, 0xa3, 0x67, 0x24, 0x00, 0x00, 0x00,
+];
+
 pub fn url(req: &str) -> String {
     format!("http://localhost:35562{}", req)
 }